@@ -0,0 +1,83 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::io::Write;
+
+use serde_json::{json, Value};
+
+use u4pak::conflicts::{conflicts, Conflict};
+use u4pak::util::{print_headless_table, print_table, Align::*};
+use u4pak::result::{Error, Result};
+use u4pak::pak::{Pak, HexDisplay};
+use u4pak::ReadAt;
+
+#[derive(Debug, PartialEq)]
+pub enum ConflictsStyle {
+    Table { no_header: bool },
+    Json,
+}
+
+pub struct ConflictsOptions {
+    pub style: ConflictsStyle,
+    /// Skip paths where all copies are identical.
+    pub only_different: bool,
+}
+
+/// Reports the paths that are in more than one of `paks`, which are in load
+/// order. `pak_paths` are the names of the paks in the output.
+pub fn print_conflicts<R>(pak_paths: &[&str], paks: &[Pak], readers: &[R], options: ConflictsOptions) -> Result<()>
+where R: ReadAt {
+    let conflicts: Vec<Conflict> = conflicts(paks, readers)?.into_iter()
+        .filter(|conflict| !options.only_different || conflict.identical() != Some(true))
+        .collect();
+
+    match options.style {
+        ConflictsStyle::Table { no_header } => {
+            let mut body: Vec<Vec<String>> = Vec::new();
+
+            for conflict in &conflicts {
+                for (index, copy) in conflict.copies().iter().enumerate() {
+                    body.push(vec![
+                        conflict.state(index).name().to_owned(),
+                        copy.sha1().map_or_else(|| "-".to_owned(), |sha1| HexDisplay::new(sha1).to_string()),
+                        pak_paths[copy.pak_index()].to_owned(),
+                        conflict.path().to_owned(),
+                    ]);
+                }
+            }
+
+            let align = [Left, Left, Left, Left];
+            if no_header {
+                print_headless_table(&body, &align);
+            } else {
+                print_table(&["Status", "SHA-1", "Pak", "Path"], &align, &body);
+            }
+        }
+        ConflictsStyle::Json => {
+            let value = Value::Array(conflicts.iter().map(|conflict| {
+                let copies: Vec<Value> = conflict.copies().iter().enumerate().map(|(index, copy)| json!({
+                    "pak": pak_paths[copy.pak_index()],
+                    "sha1": copy.sha1().map(|sha1| HexDisplay::new(sha1).to_string()),
+                    "status": conflict.state(index).name(),
+                })).collect();
+
+                json!({
+                    "path": conflict.path(),
+                    "winner": pak_paths[conflict.winner().pak_index()],
+                    "identical": conflict.identical(),
+                    "copies": copies,
+                })
+            }).collect());
+
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &value)
+                .map_err(|error| Error::new(error.to_string()))?;
+            stdout.write_all(b"\n")?;
+        }
+    }
+
+    Ok(())
+}
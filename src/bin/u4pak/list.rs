@@ -12,8 +12,9 @@ use u4pak::{Filter, util::print_headless_table};
 use u4pak::util::{format_size, print_table, Align::*};
 use u4pak::result::Result;
 use u4pak::record::Record;
-use u4pak::pak::{Pak, compression_method_name, HexDisplay};
+use u4pak::pak::{Pak, HexDisplay};
 use u4pak::check::NULL_SHA1;
+use u4pak::pakset::{PakSet, PakSetEntry};
 use crate::sort::{sort, Order};
 
 #[derive(Debug, PartialEq)]
@@ -54,7 +55,6 @@ impl Default for ListOptions<'_> {
 }
 
 pub fn list(pak: Pak, options: ListOptions) -> Result<()> {
-    let version = pak.version();
     match (options.order, options.paths) {
         (Some(order), Some(paths)) => {
             let mut filter = Filter::from_paths(paths.iter().cloned());
@@ -64,14 +64,14 @@ pub fn list(pak: Pak, options: ListOptions) -> Result<()> {
                 .collect();
 
             sort(&mut records, order);
-            list_records(version, &records, options)?;
+            list_records(&pak, &records, options)?;
             filter.assert_all_visited()?;
         }
         (Some(order), None) => {
             let mut records = pak.index().records().iter().collect();
 
             sort(&mut records, order);
-            list_records(version, &records, options)?;
+            list_records(&pak, &records, options)?;
         }
         (None, Some(paths)) => {
             let mut filter = Filter::from_paths(paths.iter().cloned());
@@ -80,18 +80,84 @@ pub fn list(pak: Pak, options: ListOptions) -> Result<()> {
                 .filter(|record| filter.visit(record.filename()))
                 .collect::<Vec<_>>();
 
-            list_records(version, &records, options)?;
+            list_records(&pak, &records, options)?;
             filter.assert_all_visited()?;
         }
         (None, None) => {
-            list_records(version, pak.index().records(), options)?;
+            list_records(&pak, pak.index().records(), options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the winning records of a [`PakSet`] and the paks they come from.
+pub fn list_set(set: &PakSet, options: ListOptions) -> Result<()> {
+    let mut filter = options.paths.map(|paths| Filter::from_paths(paths.iter().cloned()));
+    let mut entries = match &mut filter {
+        Some(filter) => set.entries()
+            .filter(|entry| filter.visit(entry.path()))
+            .collect(),
+        None => set.entries().collect(),
+    };
+
+    if let Some(order) = options.order {
+        sort(&mut entries, order);
+    }
+
+    list_set_entries(&entries, options)?;
+
+    if let Some(filter) = filter {
+        filter.assert_all_visited()?;
+    }
+
+    Ok(())
+}
+
+fn list_set_entries(entries: &[PakSetEntry], options: ListOptions) -> Result<()> {
+    match options.style {
+        ListStyle::Table { human_readable, no_header } => {
+            let fmt_size = if human_readable {
+                |size: u64| format_size(size)
+            } else {
+                |size: u64| format!("{}", size)
+            };
+
+            let body: Vec<Vec<String>> = entries.iter().map(|entry| {
+                let record = entry.record();
+                let pak = entry.layer().pak();
+                vec![
+                    fmt_size(record.uncompressed_size()),
+                    fmt_size(record.size()),
+                    pak.compression_method_name(record.compression_method()).to_owned(),
+                    HexDisplay::new(record.sha1().as_ref().unwrap_or(&NULL_SHA1)).to_string(),
+                    entry.layer().path().file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                    entry.path().to_owned(),
+                ]
+            }).collect();
+
+            let align = [Right, Right, Left, Left, Left, Left];
+            if no_header {
+                print_headless_table(&body, &align);
+            } else {
+                print_table(&["Size", "Compr.", "Method", "SHA-1", "Pak", "Path"], &align, &body);
+            }
+        }
+        ListStyle::OnlyNames { null_separated } => {
+            let sep = [if null_separated { 0 } else { b'\n' }];
+            let mut stdout = std::io::stdout();
+            for entry in entries {
+                stdout.write_all(entry.path().as_bytes())?;
+                stdout.write_all(&sep)?;
+            }
         }
     }
 
     Ok(())
 }
 
-fn list_records(version: u32, records: &[impl AsRef<Record>], options: ListOptions) -> Result<()> {
+fn list_records(pak: &Pak, records: &[impl AsRef<Record>], options: ListOptions) -> Result<()> {
+    let version = pak.version();
     match options.style {
         ListStyle::Table { human_readable, no_header } => {
             let mut body: Vec<Vec<String>> = Vec::new();
@@ -108,7 +174,7 @@ fn list_records(version: u32, records: &[impl AsRef<Record>], options: ListOptio
                     format!("{}", record.offset()),
                     fmt_size(record.uncompressed_size()),
                     fmt_size(record.size()),
-                    compression_method_name(record.compression_method()).to_owned(),
+                    pak.compression_method_name(record.compression_method()).to_owned(),
                     fmt_size(record.compression_block_size() as u64),
                 ];
                 if version == 1 {
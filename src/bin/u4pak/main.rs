@@ -12,8 +12,10 @@ use std::fs::File;
 use std::io::BufReader;
 use std::{
     convert::TryInto,
-    io::stderr,
+    io::{stderr, stdout, BufWriter, Write},
     num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    path::Path,
+    sync::Arc,
 };
 
 #[cfg(target_family = "windows")]
@@ -22,22 +24,30 @@ use std::convert::TryFrom;
 use u4pak::check::{check, CheckOptions};
 use u4pak::info::info;
 use u4pak::pack::{pack, PackOptions, PackPath};
-use u4pak::pak::{Options, COMPR_NONE, COMPR_ZLIB};
+use u4pak::codec::CodecRegistry;
+use u4pak::keyring::Keyring;
+use u4pak::dictionary::Dictionary;
+use u4pak::pak::{Options, COMPR_NONE};
+use u4pak::plugin::load_codec_plugin;
 use u4pak::unpack::{unpack, UnpackOptions};
-use u4pak::util::{parse_compression_level, parse_size};
-use u4pak::{Error, Pak, Result, Variant};
+use u4pak::util::{parse_compression_level, parse_guid, parse_size};
+use u4pak::http::{is_http_url, HttpOptions, HttpReader};
+use u4pak::{Error, Pak, PakSet, ReadAt, ReadAtCursor, Result, Variant};
 
 pub mod sort;
 use sort::parse_order;
 
 mod list;
-use list::{list, ListOptions, ListStyle};
+use list::{list, list_set, ListOptions, ListStyle};
+
+mod conflicts;
+use conflicts::{print_conflicts, ConflictsOptions, ConflictsStyle};
 
 pub mod args;
 pub mod io;
 
 #[cfg(target_os = "linux")]
-pub use u4pak::mount::{mount, MountOptions};
+pub use u4pak::mount::{mount, mount_set, MountOptions};
 
 fn get_paths<'a>(args: &'a clap::ArgMatches) -> Result<Option<Vec<&'a str>>> {
     if let Some(arg_paths) = args.values_of("paths") {
@@ -79,11 +89,54 @@ fn get_threads(args: &clap::ArgMatches) -> Result<NonZeroUsize> {
     Ok(threads.unwrap_or_else(|| NonZeroUsize::new(1).unwrap()))
 }
 
-pub fn parse_compression_method(value: &str) -> Result<u32> {
+// Pak files are read directly, http:// and https:// URLs via range requests.
+fn open_package(path: &str) -> Result<Box<dyn ReadAt + Sync>> {
+    if is_http_url(path) {
+        Ok(Box::new(HttpReader::open(path, HttpOptions::default())?))
+    } else {
+        match File::open(path) {
+            Ok(file) => Ok(Box::new(file)),
+            Err(error) => Err(Error::io_with_path(error, path)),
+        }
+    }
+}
+
+// A directory is read as a set of layered paks.
+fn is_pak_dir(path: &str) -> bool {
+    !is_http_url(path) && Path::new(path).is_dir()
+}
+
+fn get_codecs(args: &clap::ArgMatches) -> Result<Arc<CodecRegistry>> {
+    let mut codecs = CodecRegistry::default();
+    if let Some(plugins) = args.values_of("codec-plugin") {
+        for plugin in plugins {
+            load_codec_plugin(&mut codecs, plugin)?;
+        }
+    }
+    Ok(Arc::new(codecs))
+}
+
+fn get_keyring(args: &clap::ArgMatches) -> Result<Arc<Keyring>> {
+    if let Some(path) = args.value_of("keyring") {
+        Ok(Arc::new(Keyring::from_path(path)?))
+    } else {
+        Ok(Arc::new(Keyring::default()))
+    }
+}
+
+fn get_dictionary(args: &clap::ArgMatches) -> Result<Arc<Dictionary>> {
+    if let Some(path) = args.value_of("dictionary") {
+        Ok(Arc::new(Dictionary::from_path(path)?))
+    } else {
+        Ok(Arc::new(Dictionary::default()))
+    }
+}
+
+pub fn parse_compression_method(value: &str, codecs: &CodecRegistry) -> Result<u32> {
     if value.eq_ignore_ascii_case("none") {
         Ok(COMPR_NONE)
-    } else if value.eq_ignore_ascii_case("zlib") {
-        Ok(COMPR_ZLIB)
+    } else if let Some(codec) = codecs.by_name(value) {
+        Ok(codec.method())
     } else {
         Err(Error::new(format!(
             "compression method not supported: {:?}",
@@ -108,6 +161,20 @@ fn arg_package<'a, 'b>() -> Arg<'a, 'b> {
         .help("An Unreal Engine 4 pak file")
 }
 
+fn arg_package_or_url<'a, 'b>() -> Arg<'a, 'b> {
+    arg_package()
+        .help("An Unreal Engine 4 pak file or an http:// or https:// URL of one. \
+               Of URLs only the needed parts are downloaded via range requests.")
+}
+
+fn arg_package_url_or_dir<'a, 'b>() -> Arg<'a, 'b> {
+    arg_package()
+        .help("An Unreal Engine 4 pak file, an http:// or https:// URL of one, or a directory. \
+               The paks of a directory are layered like the engine mounts them: patch paks \
+               (*_P.pak) override others, then higher chunk numbers (pakchunkN*.pak) override \
+               lower ones. Of URLs only the needed parts are downloaded via range requests.")
+}
+
 fn arg_paths<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("paths")
         .index(2)
@@ -191,13 +258,48 @@ fn arg_print0<'a, 'b>() -> Arg<'a, 'b> {
         )
 }
 
+fn arg_codec_plugin<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("codec-plugin")
+        .long("codec-plugin")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .value_name("PATH")
+        .help(
+            "Load a compression codec from a shared library. Can be given multiple times. \
+            Codecs loaded like this can only be used for packing version 8 and up.")
+}
+
 fn arg_encryption_key<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("encryption-key")
         .long("encryption-key")
         .short("k")
         .takes_value(true)
         .value_name("ENCRYPTION_KEY")
-        .help("Base64 encoded 16 byte AES encryption key")
+        .help("Base64 encoded 32 byte AES-256 encryption key")
+}
+
+fn arg_dictionary<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("dictionary")
+        .long("dictionary")
+        .takes_value(true)
+        .value_name("PATH")
+        .help(
+            "Text file with one path per line. Used to restore the file names of packages \
+            that only have a path hash index (version 10 and up). Paths that are not found \
+            are shown as their hash.")
+}
+
+fn arg_keyring<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("keyring")
+        .long("keyring")
+        .takes_value(true)
+        .value_name("PATH")
+        .help(
+            "JSON or TOML file (detected by the .toml extension) that maps encryption key GUIDs \
+            to base64 encoded keys. The key is selected by the GUID in the footer of the package. \
+            Packages before version 7 use the GUID 00000000000000000000000000000000. \
+            If there is no matching key --encryption-key is used.")
 }
 
 #[cfg(target_family = "windows")]
@@ -252,7 +354,7 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
         .about("\n\
                 This is a tool to pack, unpack, check, and list the contents of Unreal Engine 4 packages. \
                 Note that only a limited number of pak versions are supported, depending on the kinds of \
-                paks I have seen.For reading that is version 1-11, for writing it is 1, 2, 3.\n\
+                paks I have seen.For reading and writing that is version 1-11.\n\
                 \n\
                 Instead of passing arguments you can also put the arguments in a file with the extension \
                 .u4pak and pass the path to that instead. This is useful for Windows users that aren't \
@@ -306,8 +408,11 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
             .arg(arg_ignore_magic())
             .arg(arg_encoding())
             .arg(arg_force_version())
-            .arg(arg_package())
-            .arg(arg_encryption_key()))
+            .arg(arg_package_or_url())
+            .arg(arg_encryption_key())
+            .arg(arg_keyring())
+            .arg(arg_dictionary())
+            .arg(arg_codec_plugin()))
         .subcommand(SubCommand::with_name("list")
             .alias("l")
             .about("List content of a package")
@@ -352,9 +457,55 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
             .arg(arg_force_version())
             .arg(arg_human_readable())
             .arg(arg_threads())
-            .arg(arg_package())
+            .arg(arg_package_url_or_dir())
             .arg(arg_paths())
-            .arg(arg_encryption_key()))
+            .arg(arg_encryption_key())
+            .arg(arg_keyring())
+            .arg(arg_dictionary())
+            .arg(arg_codec_plugin()))
+        .subcommand(SubCommand::with_name("conflicts")
+            .about("Report files that are in more than one of the given packages")
+            .long_about(
+                "Report files that are in more than one of the given packages\n\
+                \n\
+                The packages are given in load order, so the copy of the last package that \
+                contains a file wins. Paths include the mount points of the packages. Every \
+                overridden copy is compared to the winning copy by the SHA-1 of its record \
+                (i.e. of the data as it is stored in the package) and marked as identical, \
+                differs, or unknown if a checksum is missing.\n\
+                \n\
+                Example:\n\
+                \tu4pak conflicts --json Game.pak Game_P.pak ModA.pak ModB.pak")
+            .arg(arg_variant())
+            .arg(Arg::with_name("json")
+                .long("json")
+                .short("j")
+                .takes_value(false)
+                .help("Print the report as JSON."))
+            .arg(Arg::with_name("no-header")
+                .long("no-header")
+                .short("H")
+                .takes_value(false)
+                .conflicts_with("json")
+                .help("Don't print table header"))
+            .arg(Arg::with_name("only-different")
+                .long("only-different")
+                .short("d")
+                .takes_value(false)
+                .help("Skip files where all copies are identical."))
+            .arg(arg_ignore_magic())
+            .arg(arg_encoding())
+            .arg(arg_force_version())
+            .arg(arg_encryption_key())
+            .arg(arg_keyring())
+            .arg(arg_dictionary())
+            .arg(arg_codec_plugin())
+            .arg(Arg::with_name("packages")
+                .index(1)
+                .required(true)
+                .multiple(true)
+                .value_name("PACKAGE")
+                .help("Unreal Engine 4 pak files or http:// or https:// URLs of them in load order.")))
         .subcommand(SubCommand::with_name("check")
             .alias("c")
             .about("Check consistency of a package")
@@ -368,11 +519,61 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
             .arg(arg_encoding())
             .arg(arg_force_version())
             .arg(arg_ignore_null_checksums())
+            .arg(Arg::with_name("deep")
+                .long("deep")
+                .takes_value(false)
+                .help("Decompress all files and check the decompressed sizes. This is slow."))
             .arg(arg_threads())
             .arg(arg_verbose())
-            .arg(arg_package())
+            .arg(arg_package_or_url())
             .arg(arg_paths())
-            .arg(arg_encryption_key()))
+            .arg(arg_encryption_key())
+            .arg(arg_keyring())
+            .arg(arg_dictionary())
+            .arg(arg_codec_plugin()))
+        .subcommand(SubCommand::with_name("dictionary")
+            .alias("d")
+            .about("Export the paths of packages as a dictionary for packages without full directory index")
+            .long_about(
+                "Export the paths of packages as a dictionary for packages without full directory index\n\
+                \n\
+                Writes the paths of all given packages (and of the dictionary given via --dictionary) \
+                to a text file, one per line. Each path is also written in its lower case form. Such \
+                a file can then be passed via --dictionary to the other sub-commands in order to restore \
+                the file names of packages that only have a path hash index (version 10 and up).\n\
+                \n\
+                Example:\n\
+                \tu4pak dictionary --output=paths.txt Old1.pak Old2.pak\n\
+                \tu4pak dictionary --dictionary=paths.txt --coverage=New.pak\n\
+                \tu4pak unpack --dictionary=paths.txt New.pak")
+            .arg(arg_variant())
+            .arg(arg_ignore_magic())
+            .arg(arg_encoding())
+            .arg(arg_force_version())
+            .arg(arg_encryption_key())
+            .arg(arg_keyring())
+            .arg(Arg::with_name("dictionary")
+                .long("dictionary")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Existing dictionary to add the paths of the packages to."))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Write the dictionary to PATH. Per default it is written to standard output, \
+                       unless --coverage is given."))
+            .arg(Arg::with_name("coverage")
+                .long("coverage")
+                .takes_value(true)
+                .value_name("PACKAGE")
+                .help("Report how many entries of the path hash index of PACKAGE are found in the dictionary."))
+            .arg(Arg::with_name("packages")
+                .index(1)
+                .multiple(true)
+                .value_name("PACKAGE")
+                .help("Unreal Engine 4 pak files with full directory index.")))
         .subcommand(SubCommand::with_name("unpack")
             .alias("u")
             .about("Unpack content of a package")
@@ -389,7 +590,8 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(false)
                 .help(
                     "Put files that where compressed into separate folders. \
-                     The folder names will be 'none' and 'zlib'."))
+                     The folder names will be 'none' and the lower case compression method name (e.g. 'zlib'). \
+                     Files of old paks that use the bias methods go into 'zlib'."))
             .arg(Arg::with_name("outdir")
                 .long("outdir")
                 .short("o")
@@ -397,9 +599,12 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
                 .value_name("DIR")
                 .default_value(".")
                 .help("Write unpacked files to DIR."))
-            .arg(arg_package())
+            .arg(arg_package_url_or_dir())
             .arg(arg_paths())
-            .arg(arg_encryption_key()))
+            .arg(arg_encryption_key())
+            .arg(arg_keyring())
+            .arg(arg_dictionary())
+            .arg(arg_codec_plugin()))
         .subcommand(SubCommand::with_name("pack")
             .alias("p")
             .about("Create a new package")
@@ -409,7 +614,7 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
                 .short("V")
                 .takes_value(true)
                 .help(
-                    "Create package of given VERSION. Supported versions are: 1, 2, and 3 \
+                    "Create package of given VERSION. Supported versions are: 1 to 11 \
                     [default: 3 when --variant=standard, 4 when --variant=conan_exiles]"))
             .arg(Arg::with_name("mount-point")
                 .long("mount-point")
@@ -421,7 +626,9 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
                 .short("c")
                 .takes_value(true)
                 .default_value("none")
-                .help("Default compression method. See also: --compression-min-size"))
+                .help("Default compression method. Supported methods are: none, zlib, gzip, lz4 (only version 8 and up), \
+                       and the names of codecs loaded with --codec-plugin. \
+                       See also: --compression-min-size"))
             .arg(Arg::with_name("compression-block-size")
                 .long("compression-block-size")
                 .short("b")
@@ -445,10 +652,27 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
                     "Minimum size of files to be compressed. Note that it makes no sense to \
                     try to compress files smaller than 100 bytes or so, because of the \
                     compression overhead."))
+            .arg(arg_encryption_key()
+                .help("Encrypt file data and index with this base64 encoded 32 byte AES-256 key. \
+                       Data encryption needs version 3 and up, index encryption version 4 and up."))
+            .arg(Arg::with_name("encrypt-index-only")
+                .long("encrypt-index-only")
+                .takes_value(false)
+                .requires("encryption-key")
+                .help("Only encrypt the index, not the file data."))
+            .arg(Arg::with_name("encryption-key-guid")
+                .long("encryption-key-guid")
+                .takes_value(true)
+                .value_name("GUID")
+                .requires("encryption-key")
+                .help(
+                    "GUID of the encryption key as 32 hex digits, written into the footer of \
+                    version 7 and up. [default: 00000000000000000000000000000000]"))
             .arg(arg_encoding())
             .arg(arg_print0())
             .arg(arg_threads())
             .arg(arg_verbose())
+            .arg(arg_codec_plugin())
             .arg(arg_package())
             .arg(Arg::with_name("paths")
                 .index(2)
@@ -468,8 +692,8 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
                     compression block size of 65536 bytes, and rename the folder to be 'Foo/Bar' \
                     inside of the pak archive file.\n\
                     \n\
-                    Instead of 'zlib' you can also write 'none' to not compress the files from the \
-                    given path. If you don't say any of either the default value provided by \
+                    Instead of 'zlib' you can also write 'gzip', 'lz4' (only version 8 and up), or 'none' to \
+                    not compress the files from the given path. If you don't say any of either the default value provided by \
                     --compression-method is used. Same goes for all the other parameters. \
                     If you don't specify 'rename' then the same path is used for the folder inside \
                     of the pak archive as the files on your hard disk have.\n\
@@ -491,12 +715,15 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
     let app = app.subcommand(
         SubCommand::with_name("mount")
             .alias("m")
-            .about("Mount package as read-only filesystem")
+            .about("Mount package(s) as read-only filesystem")
             .arg(arg_variant())
             .arg(arg_ignore_magic())
             .arg(arg_encoding())
             .arg(arg_force_version())
             .arg(arg_encryption_key())
+            .arg(arg_keyring())
+            .arg(arg_dictionary())
+            .arg(arg_codec_plugin())
             .arg(
                 Arg::with_name("foregound")
                     .long("foreground")
@@ -511,7 +738,20 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
                     .takes_value(false)
                     .help("Debug mode. Implies --foreground."),
             )
-            .arg(arg_package())
+            .arg(
+                Arg::with_name("packages")
+                    .index(1)
+                    .required(true)
+                    .multiple(true)
+                    .value_name("PACKAGE")
+                    .help("Unreal Engine 4 pak files or directories of paks. \
+                           Several paks are mounted as one overlay that is layered like the engine mounts \
+                           them: patch paks (*_P.pak) override others, then higher chunk numbers \
+                           (pakchunkN*.pak) override lower ones, and otherwise later arguments override \
+                           earlier ones. Paths of an overlay include the \
+                           mount points of the paks and each file has the extended attribute \
+                           user.u4pak.pak with the path of the pak it comes from."),
+            )
             .arg(
                 Arg::with_name("mountpt")
                     .index(2)
@@ -613,18 +853,24 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 None
             };
 
-            let pak = Pak::from_path(
-                &path,
-                Options {
-                    variant,
-                    ignore_magic,
-                    encoding,
-                    force_version,
-                    encryption_key,
-                },
-            )?;
+            let options = Options {
+                variant,
+                ignore_magic,
+                encoding,
+                force_version,
+                encryption_key,
+                keyring: get_keyring(args)?,
+                codecs: get_codecs(args)?,
+                dictionary: get_dictionary(args)?,
+            };
+
+            let source = open_package(path)?;
+            let mut reader = BufReader::new(ReadAtCursor::new(&source));
+            let footer = Pak::read_footer(&mut reader, &options)?;
+            let keyring = options.keyring.clone();
+            let pak = Pak::from_reader(&mut reader, options);
 
-            info(&pak, human_readable)?;
+            info(&footer, &pak, &keyring, human_readable)?;
         }
         ("list", Some(args)) => {
             let order = if let Some(order) = args.value_of("sort") {
@@ -667,46 +913,105 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 None
             };
 
-            let mut file = match File::open(path) {
-                Ok(file) => file,
-                Err(error) => return Err(Error::io_with_path(error, path)),
+            let options = Options {
+                variant,
+                ignore_magic,
+                encoding,
+                force_version,
+                encryption_key,
+                keyring: get_keyring(args)?,
+                codecs: get_codecs(args)?,
+                dictionary: get_dictionary(args)?,
             };
-            let mut reader = BufReader::new(&mut file);
 
-            let pak = Pak::from_reader(
-                &mut reader,
-                Options {
-                    variant,
-                    ignore_magic,
-                    encoding,
-                    force_version,
-                    encryption_key,
+            let list_options = ListOptions {
+                order,
+                style: if only_names {
+                    ListStyle::OnlyNames { null_separated }
+                } else {
+                    ListStyle::Table {
+                        human_readable,
+                        no_header,
+                    }
                 },
-            )?;
+                paths,
+            };
 
-            drop(reader);
-
-            list(
-                pak,
-                ListOptions {
-                    order,
-                    style: if only_names {
-                        ListStyle::OnlyNames { null_separated }
-                    } else {
-                        ListStyle::Table {
-                            human_readable,
-                            no_header,
-                        }
-                    },
-                    paths,
+            if is_pak_dir(path) {
+                list_set(&PakSet::from_dir(path, options)?, list_options)?;
+            } else {
+                let source = open_package(path)?;
+                let mut reader = BufReader::new(ReadAtCursor::new(&source));
+
+                let pak = Pak::from_reader(&mut reader, options)?;
+
+                drop(reader);
+
+                list(pak, list_options)?;
+            }
+        }
+        ("conflicts", Some(args)) => {
+            let variant = args.value_of("variant").unwrap().try_into()?;
+            let ignore_magic = args.is_present("ignore-magic");
+            let encoding = args.value_of("encoding").unwrap().try_into()?;
+            let no_header = args.is_present("no-header");
+            let only_different = args.is_present("only-different");
+            let paths: Vec<&str> = args.values_of("packages").unwrap().collect();
+
+            let force_version = if let Some(version) = args.value_of("force-version") {
+                Some(version.parse()?)
+            } else {
+                None
+            };
+
+            let encryption_key = if let Some(key) = args.value_of("encryption-key") {
+                Some(
+                    base64::decode(
+                        key.parse::<String>()
+                            .expect("Failed to read encryption key."),
+                    )
+                    .expect("Failed to parse encryption key."),
+                )
+            } else {
+                None
+            };
+
+            let options = Options {
+                variant,
+                ignore_magic,
+                encoding,
+                force_version,
+                encryption_key,
+                keyring: get_keyring(args)?,
+                codecs: get_codecs(args)?,
+                dictionary: get_dictionary(args)?,
+            };
+
+            let mut sources = Vec::with_capacity(paths.len());
+            let mut paks = Vec::with_capacity(paths.len());
+            for &path in &paths {
+                let source = open_package(path)?;
+                let pak = Pak::from_reader(&mut BufReader::new(ReadAtCursor::new(&source)), options.clone())
+                    .map_err(|error| error.with_path_if_none(path))?;
+                sources.push(source);
+                paks.push(pak);
+            }
+
+            print_conflicts(&paths, &paks, &sources, ConflictsOptions {
+                style: if args.is_present("json") {
+                    ConflictsStyle::Json
+                } else {
+                    ConflictsStyle::Table { no_header }
                 },
-            )?;
+                only_different,
+            })?;
         }
         ("check", Some(args)) => {
             let null_separated = args.is_present("print0");
             let ignore_magic = args.is_present("ignore-magic");
             let ignore_null_checksums = args.is_present("ignore-null-checksums");
             let abort_on_error = args.is_present("abort-on-error");
+            let deep = args.is_present("deep");
             let verbose = args.is_present("verbose");
             let variant = args.value_of("variant").unwrap().try_into()?;
             let encoding = args.value_of("encoding").unwrap().try_into()?;
@@ -736,11 +1041,8 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 None
             };
 
-            let mut file = match File::open(path) {
-                Ok(file) => file,
-                Err(error) => return Err(Error::io_with_path(error, path)),
-            };
-            let mut reader = BufReader::new(&mut file);
+            let source = open_package(path)?;
+            let mut reader = BufReader::new(ReadAtCursor::new(&source));
 
             let pak = Pak::from_reader(
                 &mut reader,
@@ -750,6 +1052,9 @@ fn run(matches: &ArgMatches) -> Result<()> {
                     encoding,
                     force_version,
                     encryption_key,
+                    keyring: get_keyring(args)?,
+                    codecs: get_codecs(args)?,
+                    dictionary: get_dictionary(args)?,
                 },
             )?;
 
@@ -757,13 +1062,17 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 variant,
                 abort_on_error,
                 ignore_null_checksums,
+                deep,
                 null_separated,
                 verbose,
                 thread_count: get_threads(args)?,
                 paths,
+                encoding,
+                // use the key that was selected when reading the index
+                encryption_key: pak.encryption_key().map(<[u8]>::to_vec),
             };
 
-            let error_count = check(&pak, &mut file, options)?;
+            let error_count = check(&pak, &source, options)?;
 
             let sep = if null_separated { '\0' } else { '\n' };
             if error_count == 0 {
@@ -773,6 +1082,90 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 std::process::exit(1);
             }
         }
+        ("dictionary", Some(args)) => {
+            let variant = args.value_of("variant").unwrap().try_into()?;
+            let ignore_magic = args.is_present("ignore-magic");
+            let encoding = args.value_of("encoding").unwrap().try_into()?;
+            let keyring = get_keyring(args)?;
+
+            let force_version = if let Some(version) = args.value_of("force-version") {
+                Some(version.parse()?)
+            } else {
+                None
+            };
+
+            let encryption_key = if let Some(key) = args.value_of("encryption-key") {
+                Some(
+                    base64::decode(
+                        key.parse::<String>()
+                            .expect("Failed to read encryption key."),
+                    )
+                    .expect("Failed to parse encryption key."),
+                )
+            } else {
+                None
+            };
+
+            let options = Options {
+                variant,
+                ignore_magic,
+                encoding,
+                force_version,
+                encryption_key,
+                keyring,
+                ..Options::default()
+            };
+
+            // the given dictionary is extended, not used for reading the packages
+            let mut dictionary = if let Some(path) = args.value_of("dictionary") {
+                Dictionary::from_path(path)?
+            } else {
+                Dictionary::new()
+            };
+
+            if let Some(paths) = args.values_of("packages") {
+                for path in paths {
+                    let pak = Pak::from_path(path, options.clone())?;
+                    dictionary.insert_pak(&pak).map_err(|error| error.with_path(path))?;
+                }
+            }
+
+            let coverage = args.value_of("coverage");
+
+            if let Some(path) = args.value_of("output") {
+                let mut writer = BufWriter::new(match File::create(path) {
+                    Ok(file) => file,
+                    Err(error) => return Err(Error::io_with_path(error, path)),
+                });
+                if let Err(error) = dictionary.write_to(&mut writer).and_then(|_| writer.flush()) {
+                    return Err(Error::io_with_path(error, path));
+                }
+            } else if coverage.is_none() {
+                let stdout = stdout();
+                let mut writer = BufWriter::new(stdout.lock());
+                dictionary.write_to(&mut writer)?;
+                writer.flush()?;
+            }
+
+            if let Some(path) = coverage {
+                let mut file = match File::open(path) {
+                    Ok(file) => file,
+                    Err(error) => return Err(Error::io_with_path(error, path)),
+                };
+                let mut reader = BufReader::new(&mut file);
+                let pak = Pak::from_reader(&mut reader, options)?;
+                let coverage = dictionary.coverage(&pak, &mut reader)
+                    .map_err(|error| error.with_path_if_none(path))?;
+
+                let percent = if coverage.path_hash_count == 0 {
+                    100.0
+                } else {
+                    coverage.resolved_count as f64 * 100.0 / coverage.path_hash_count as f64
+                };
+                println!("{}: {} of {} path hashes found in dictionary ({:.1}%)",
+                    path, coverage.resolved_count, coverage.path_hash_count, percent);
+            }
+        }
         ("unpack", Some(args)) => {
             let variant = args.value_of("variant").unwrap().try_into()?;
             let outdir = args.value_of("outdir").unwrap();
@@ -808,38 +1201,54 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 None
             };
 
-            let mut file = match File::open(path) {
-                Ok(file) => file,
-                Err(error) => return Err(Error::io_with_path(error, path)),
+            let options = Options {
+                variant,
+                ignore_magic,
+                encoding,
+                force_version,
+                encryption_key,
+                keyring: get_keyring(args)?,
+                codecs: get_codecs(args)?,
+                dictionary: get_dictionary(args)?,
             };
-            let mut reader = BufReader::new(&mut file);
-
-            let pak = Pak::from_reader(
-                &mut reader,
-                Options {
-                    variant,
-                    ignore_magic,
-                    encoding,
-                    force_version,
-                    encryption_key: encryption_key.clone(),
-                },
-            )?;
-
-            drop(reader);
 
-            unpack(
-                &pak,
-                &mut file,
-                outdir,
-                UnpackOptions {
-                    dirname_from_compression,
-                    verbose,
-                    null_separated,
-                    paths,
-                    thread_count,
-                    encryption_key,
-                },
-            )?;
+            if is_pak_dir(path) {
+                let set = PakSet::from_dir(path, options)?;
+                // every pak falls back to the key that was used to read its index
+                set.unpack(
+                    outdir,
+                    UnpackOptions {
+                        dirname_from_compression,
+                        verbose,
+                        null_separated,
+                        paths,
+                        thread_count,
+                        encryption_key: None,
+                    },
+                )?;
+            } else {
+                let source = open_package(path)?;
+                let mut reader = BufReader::new(ReadAtCursor::new(&source));
+
+                let pak = Pak::from_reader(&mut reader, options)?;
+
+                drop(reader);
+
+                unpack(
+                    &pak,
+                    &source,
+                    outdir,
+                    UnpackOptions {
+                        dirname_from_compression,
+                        verbose,
+                        null_separated,
+                        paths,
+                        thread_count,
+                        // falls back to the key that was used to read the index
+                        encryption_key: None,
+                    },
+                )?;
+            }
         }
         ("pack", Some(args)) => {
             let variant = args.value_of("variant").unwrap().try_into()?;
@@ -889,10 +1298,28 @@ fn run(matches: &ArgMatches) -> Result<()> {
                         compression_min_size
                     )));
                 };
+            let codecs = get_codecs(args)?;
             let compression_method =
-                parse_compression_method(args.value_of("compression-method").unwrap())?;
+                parse_compression_method(args.value_of("compression-method").unwrap(), &codecs)?;
             let compression_level =
                 parse_compression_level(args.value_of("compression-level").unwrap())?;
+            let encryption_key = if let Some(key) = args.value_of("encryption-key") {
+                Some(
+                    base64::decode(
+                        key.parse::<String>()
+                            .expect("Failed to read encryption key."),
+                    )
+                    .expect("Failed to parse encryption key."),
+                )
+            } else {
+                None
+            };
+            let encrypt_index_only = args.is_present("encrypt-index-only");
+            let encryption_key_guid = if let Some(guid) = args.value_of("encryption-key-guid") {
+                parse_guid(guid)?
+            } else {
+                0
+            };
             let path = args.value_of("package").unwrap();
             let paths = if let Some(path_strs) = args.values_of("paths") {
                 let mut paths = Vec::<PackPath>::new();
@@ -921,6 +1348,10 @@ fn run(matches: &ArgMatches) -> Result<()> {
                     verbose,
                     null_separated,
                     thread_count,
+                    codecs,
+                    encryption_key,
+                    encrypt_index_only,
+                    encryption_key_guid,
                 },
             )?;
         }
@@ -931,7 +1362,7 @@ fn run(matches: &ArgMatches) -> Result<()> {
             let ignore_magic = args.is_present("ignore-magic");
             let variant = args.value_of("variant").unwrap().try_into()?;
             let encoding = args.value_of("encoding").unwrap().try_into()?;
-            let path = args.value_of("package").unwrap();
+            let paths: Vec<&str> = args.values_of("packages").unwrap().collect();
             let mountpt = args.value_of("mountpt").unwrap();
 
             let force_version = if let Some(version) = args.value_of("force-version") {
@@ -952,27 +1383,47 @@ fn run(matches: &ArgMatches) -> Result<()> {
                 None
             };
 
-            let mut file = match File::open(path) {
-                Ok(file) => file,
-                Err(error) => return Err(Error::io_with_path(error, path)),
+            let options = Options {
+                variant,
+                ignore_magic,
+                encoding,
+                force_version,
+                encryption_key,
+                keyring: get_keyring(args)?,
+                codecs: get_codecs(args)?,
+                dictionary: get_dictionary(args)?,
             };
-            let mut reader = BufReader::new(&mut file);
 
-            let pak = Pak::from_reader(
-                &mut reader,
-                Options {
-                    variant,
-                    ignore_magic,
-                    encoding,
-                    force_version,
-                    encryption_key,
-                },
-            )?;
+            if let [path] = paths[..] {
+                if !Path::new(path).is_dir() {
+                    let mut file = match File::open(path) {
+                        Ok(file) => file,
+                        Err(error) => return Err(Error::io_with_path(error, path)),
+                    };
+                    let mut reader = BufReader::new(&mut file);
+
+                    let pak = Pak::from_reader(&mut reader, options)?;
+
+                    drop(reader);
 
-            drop(reader);
+                    mount(pak, file, mountpt, MountOptions { foreground, debug })
+                        .map_err(|error| error.with_path_if_none(path))?;
+
+                    return Ok(());
+                }
+            }
+
+            // the argument order is the explicit priority
+            let mut set = PakSet::new();
+            for (index, &path) in paths.iter().enumerate() {
+                if Path::new(path).is_dir() {
+                    set.insert_dir(path, options.clone(), index as i32)?;
+                } else {
+                    set.insert(path, Pak::from_path(path, options.clone())?, index as i32);
+                }
+            }
 
-            mount(pak, file, mountpt, MountOptions { foreground, debug })
-                .map_err(|error| error.with_path_if_none(path))?;
+            mount_set(set, mountpt, MountOptions { foreground, debug })?;
         }
         ("", _) => {
             let mut buf = Vec::new();
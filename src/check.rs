@@ -4,14 +4,19 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{collections::HashSet, fs::File, io::{BufReader, Read, Seek, SeekFrom, stderr}, num::NonZeroUsize};
+use std::{collections::{HashMap, HashSet}, io::{BufReader, Read, Seek, SeekFrom, stderr}, num::NonZeroUsize};
 
+use aes::BLOCK_SIZE;
 use crossbeam_channel::{Sender, unbounded};
 use crossbeam_utils::thread;
 use openssl::sha::Sha1 as OpenSSLSha1;
 
-use crate::{Error, Filter, Pak, pak::{BUFFER_SIZE, COMPR_METHODS, COMPR_NONE, HexDisplay, Sha1, Variant}};
-use crate::reopen::Reopen;
+use crate::{Error, Filter, Pak, pak::{BUFFER_SIZE, COMPR_METHODS, COMPR_NONE, HexDisplay, PAK_COMPRESSION_METHOD_NAMES_VERSION, PAK_MAX_SUPPORTED_VERSION, PAK_PATH_HASH_INDEX_VERSION, PAK_RELATIVE_COMPRESSION_OFFSET_VERSION, Sha1, Variant}};
+use crate::codec::Codec;
+use crate::decrypt::decrypt;
+use crate::index::{Encoding, path_hash, read_full_directory_index, read_path_hash_index};
+use crate::read_at::{ReadAt, ReadAtCursor};
+use crate::util::align;
 use crate::{Record, Result};
 
 pub const NULL_SHA1: Sha1 = [0u8; 20];
@@ -21,10 +26,17 @@ pub struct CheckOptions<'a> {
     pub variant: Variant,
     pub abort_on_error: bool,
     pub ignore_null_checksums: bool,
+    /// Decompress every compression block and check the decompressed sizes
+    /// and for trailing garbage after the compressed streams.
+    pub deep: bool,
     pub null_separated: bool,
     pub verbose: bool,
     pub paths: Option<&'a [&'a str]>,
     pub thread_count: NonZeroUsize,
+    pub encoding: Encoding,
+    /// Key used to decrypt encrypted records. Defaults to the key that was
+    /// used to read the index of the pak.
+    pub encryption_key: Option<Vec<u8>>,
 }
 
 impl Default for CheckOptions<'_> {
@@ -33,10 +45,13 @@ impl Default for CheckOptions<'_> {
             variant: Variant::default(),
             abort_on_error: false,
             ignore_null_checksums: false,
+            deep: false,
             null_separated: false,
             verbose: false,
             paths: None,
             thread_count: NonZeroUsize::new(num_cpus::get()).unwrap_or(NonZeroUsize::new(1).unwrap()),
+            encoding: Encoding::default(),
+            encryption_key: None,
         }
     }
 }
@@ -103,25 +118,192 @@ where R: Read, R: Seek {
     Ok(())
 }
 
+fn verify_hash(name: &str, data: &[u8], checksum: &Sha1, ignore_null_checksums: bool) -> Result<()> {
+    if ignore_null_checksums && checksum == &NULL_SHA1 {
+        return Ok(());
+    }
+    let mut hasher = OpenSSLSha1::new();
+    hasher.update(data);
+    let actual_digest = hasher.finish();
+    if &actual_digest != checksum {
+        return Err(Error::new(format!(
+            "checksum missmatch:\n\
+             \texpected: {}\n\
+             \tactual:   {}",
+             HexDisplay::new(checksum),
+             HexDisplay::new(&actual_digest)
+        )).with_path(name));
+    }
+    Ok(())
+}
 
-pub fn check<'a>(pak: &'a Pak, in_file: &mut File, options: CheckOptions) -> Result<usize> {
+// Decompresses a whole block, all of the input has to be part of the compressed stream.
+fn inflate_block(codec: &dyn Codec, input: &[u8], uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()> {
+    let consumed = codec.decompress_block_consumed(input, uncompressed_size, output)?;
+    if consumed < input.len() {
+        return Err(Error::new(format!(
+            "{} bytes of trailing garbage after the compressed data",
+            input.len() - consumed)));
+    }
+    Ok(())
+}
+
+// Verifies the hashes of the path hash index and the full directory index and
+// that the path hashes match the paths of the full directory index. Returns
+// the found errors.
+// Uncompressed encrypted data can't be verified by decompressing it and its hash
+// is over the encrypted data, so only the padding of the last AES block can tell
+// whether the key is right. It is either zeros or, like Unreal Engine writes it,
+// a repetition of the data.
+fn plausible_padding<R>(reader: &mut R, offset: u64, size: u64, key: &[u8]) -> std::io::Result<bool>
+where R: Read, R: Seek {
+    let block_size = BLOCK_SIZE as u64;
+    let pad_start = (size % block_size) as usize;
+    if pad_start == 0 {
+        return Ok(true);
+    }
+
+    let mut first_block = vec![0u8; BLOCK_SIZE];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut first_block)?;
+    decrypt(&mut first_block, key);
+
+    let mut last_block = vec![0u8; BLOCK_SIZE];
+    reader.seek(SeekFrom::Start(offset + align(size, block_size) - block_size))?;
+    reader.read_exact(&mut last_block)?;
+    decrypt(&mut last_block, key);
+
+    let padding = &last_block[pad_start..];
+    Ok(padding.iter().all(|&byte| byte == 0) ||
+       padding.iter().enumerate().all(|(index, &byte)| byte == first_block[index % size as usize]))
+}
+
+fn check_secondary_index<R>(pak: &Pak, reader: &mut R, encoding: Encoding, ignore_null_checksums: bool) -> Result<Vec<Error>>
+where R: Read, R: Seek {
+    let mut errors = Vec::new();
+    let info = if let Some(info) = pak.index().secondary_index() {
+        info
+    } else {
+        return Ok(errors);
+    };
+
+    let path_hash_index = if info.has_path_hash_index() {
+        let data = pak.read_index_data(reader, info.path_hash_index_offset(), info.path_hash_index_size())?;
+        if let Err(error) = verify_hash("<path hash index>", &data, info.path_hash_index_hash(), ignore_null_checksums) {
+            errors.push(error);
+        }
+        Some(data)
+    } else {
+        None
+    };
+
+    let full_directory_index = if info.has_full_directory_index() {
+        let data = pak.read_index_data(reader, info.full_directory_index_offset(), info.full_directory_index_size())?;
+        if let Err(error) = verify_hash("<full directory index>", &data, info.full_directory_index_hash(), ignore_null_checksums) {
+            errors.push(error);
+        }
+        Some(data)
+    } else {
+        None
+    };
+
+    if let (Some(path_hash_index), Some(full_directory_index)) = (&path_hash_index, &full_directory_index) {
+        let path_hash_index = match read_path_hash_index(path_hash_index) {
+            Ok(entries) => entries,
+            Err(error) => {
+                errors.push(error.with_path("<path hash index>"));
+                return Ok(errors);
+            }
+        };
+
+        let full_directory_index = match read_full_directory_index(full_directory_index, encoding) {
+            Ok(entries) => entries,
+            Err(error) => {
+                errors.push(error.with_path("<full directory index>"));
+                return Ok(errors);
+            }
+        };
+
+        let mut locations: HashMap<u64, u32> = HashMap::with_capacity(path_hash_index.len());
+        for (hash, location) in path_hash_index {
+            if locations.insert(hash, location).is_some() {
+                errors.push(Error::new(format!(
+                    "path hash 0x{:016x} is not unique", hash
+                )).with_path("<path hash index>"));
+            }
+        }
+
+        let seed = info.path_hash_seed();
+        for (path, location) in &full_directory_index {
+            let hash = path_hash(path, seed, pak.version());
+            match locations.remove(&hash) {
+                Some(hash_location) if hash_location != *location => {
+                    errors.push(Error::new(format!(
+                        "path hash index and full directory index refer to different records: {} != {}",
+                        hash_location, location
+                    )).with_path(path));
+                }
+                Some(_) => {}
+                None => {
+                    errors.push(Error::new(format!(
+                        "path hash 0x{:016x} is missing in path hash index", hash
+                    )).with_path(path));
+                }
+            }
+        }
+
+        let mut unknown_hashes: Vec<_> = locations.keys().collect();
+        unknown_hashes.sort();
+        for hash in unknown_hashes {
+            errors.push(Error::new(format!(
+                "path hash 0x{:016x} doesn't match any path of the full directory index", hash
+            )).with_path("<path hash index>"));
+        }
+    }
+
+    Ok(errors)
+}
+
+pub fn check<'a, R>(pak: &'a Pak, reader: &R, options: CheckOptions) -> Result<usize>
+where R: ReadAt + Sync + ?Sized {
     let CheckOptions {
         variant,
         abort_on_error,
         ignore_null_checksums,
+        deep,
         null_separated,
         verbose,
         thread_count,
         paths,
+        encoding,
+        encryption_key,
     } = options;
+    let encryption_key = encryption_key.or_else(|| pak.encryption_key().map(<[u8]>::to_vec));
+    let encryption_key = encryption_key.as_ref();
     let mut error_count = 0usize;
-    let pak_path = in_file.path()?;
     let index_offset = pak.index_offset();
     let version = pak.version();
     let mut filter: Option<Filter> = paths.map(|paths| paths.into());
     let mut stderr = stderr();
 
-    if let Err(error) = check_data(&mut BufReader::new(in_file), "<archive index>", index_offset, pak.index_size(), pak.index_sha1(), ignore_null_checksums, &mut vec![0u8; BUFFER_SIZE]) {
+    // the hash of an encrypted index is over the decrypted (but still padded) data
+    let result = if pak.encrypted_index() {
+        pak.read_index_data(&mut BufReader::new(ReadAtCursor::new(reader)), index_offset, pak.index_size())
+            .and_then(|data| verify_hash("<archive index>", &data, pak.index_sha1(), ignore_null_checksums))
+    } else {
+        check_data(&mut BufReader::new(ReadAtCursor::new(reader)), "<archive index>", index_offset, pak.index_size(), pak.index_sha1(), ignore_null_checksums, &mut vec![0u8; BUFFER_SIZE])
+    };
+
+    if let Err(error) = result {
+        error_count += 1;
+        if abort_on_error {
+            return Err(error);
+        } else {
+            let _ = error.write_to(&mut stderr, null_separated);
+        }
+    }
+
+    for error in check_secondary_index(pak, &mut BufReader::new(ReadAtCursor::new(reader)), encoding, ignore_null_checksums)? {
         error_count += 1;
         if abort_on_error {
             return Err(error);
@@ -140,7 +322,8 @@ pub fn check<'a>(pak: &'a Pak, in_file: &mut File, options: CheckOptions) -> Res
         Variant::Standard => match version {
             1 => Record::read_v1,
             2 => Record::read_v2,
-            _ if version <= 5 || version == 7 => Record::read_v3,
+            // the inline record header of version 8 and up is still the full v3 header
+            _ if version <= PAK_MAX_SUPPORTED_VERSION => Record::read_v3,
             _ => {
                 return Err(Error::new(format!("unsupported version: {}", version)));
             }
@@ -154,16 +337,27 @@ pub fn check<'a>(pak: &'a Pak, in_file: &mut File, options: CheckOptions) -> Res
         for _ in 0..thread_count.get() {
             let work_receiver = work_receiver.clone();
             let result_sender = result_sender.clone();
-            let in_file = File::open(&pak_path)?;
 
             scope.spawn(move |_| {
-                let mut reader = BufReader::new(in_file);
+                let mut reader = BufReader::new(ReadAtCursor::new(reader));
                 let mut buffer = vec![0u8; BUFFER_SIZE];
+                let mut out_buffer = Vec::new();
 
                 while let Ok(record) = work_receiver.recv() {
                     let mut ok = true;
-
-                    if !COMPR_METHODS.contains(&record.compression_method()) {
+                    // encoded index entries have no SHA-1 hash, the one from the inline header is used instead
+                    let mut sha1 = *record.sha1();
+
+                    if version >= PAK_COMPRESSION_METHOD_NAMES_VERSION {
+                        // 1-based index into the compression method names of the footer
+                        if record.compression_method() as usize > pak.compression_methods().len() {
+                            check_error!(ok, result_sender, abort_on_error, Error::new(format!(
+                                "compression method index {} out of range, there are only {} compression methods",
+                                record.compression_method(),
+                                pak.compression_methods().len(),
+                            )).with_path(record.filename()));
+                        }
+                    } else if !COMPR_METHODS.contains(&record.compression_method()) && pak.codec(record.compression_method()).is_none() {
                         check_error!(ok, result_sender, abort_on_error, Error::new(format!(
                             "unknown compression method: 0x{:02x}",
                             record.compression_method(),
@@ -179,7 +373,12 @@ pub fn check<'a>(pak: &'a Pak, in_file: &mut File, options: CheckOptions) -> Res
                     }
 
                     let offset = record.offset() + Pak::header_size(version, variant, record);
-                    if offset + record.size() > index_offset {
+                    let padded_size = if record.encrypted() && record.compression_method() == COMPR_NONE {
+                        align(record.size(), BLOCK_SIZE as u64)
+                    } else {
+                        record.size()
+                    };
+                    if offset + padded_size > index_offset {
                         check_error!(ok, result_sender, abort_on_error, Error::new(
                             "data bleeds into index".to_string()
                         ).with_path(record.filename()));
@@ -198,107 +397,226 @@ pub fn check<'a>(pak: &'a Pak, in_file: &mut File, options: CheckOptions) -> Res
                                             .with_path(other_record.filename()));
                                 }
 
-                                if !record.same_metadata(&other_record) {
-                                    check_error!(ok, result_sender, abort_on_error,
-                                        Error::new(format!("metadata missmatch:\n{}",
-                                                record.metadata_diff(&other_record)))
-                                            .with_path(other_record.filename()));
+                                let other_record = if version >= PAK_PATH_HASH_INDEX_VERSION {
+                                    if sha1.is_none() {
+                                        sha1 = *other_record.sha1();
+                                    }
+                                    // compare with what the inline header would look like as an encoded entry
+                                    other_record.to_encoded()
+                                } else {
+                                    Ok(other_record)
+                                };
+
+                                match other_record {
+                                    Ok(other_record) => {
+                                        if !record.same_metadata(&other_record) {
+                                            check_error!(ok, result_sender, abort_on_error,
+                                                Error::new(format!("metadata missmatch:\n{}",
+                                                        record.metadata_diff(&other_record)))
+                                                    .with_path(other_record.filename()));
+                                        }
+                                    }
+                                    Err(error) => {
+                                        check_error!(ok, result_sender, abort_on_error, error.with_path_if_none(record.filename()));
+                                    }
                                 }
                             }
                             Err(error) => {
                                 check_error!(ok, result_sender, abort_on_error, error);
                             }
                         };
-                        //if version >= 4 {
-                        //    let mut f = || -> Result<()> {
-                        //        decode!(&mut reader, unknown: u32);
-                        //        println!(">>> {:20} {:15x} {}", unknown, unknown, record.filename());
-                        //        Ok(())
-                        //    };
-                        //    let _ = f();
-                        //}
                     }
 
+                    // encrypted data is stored padded to whole AES blocks, the SHA-1 hash is over the padded data
+                    let verify_checksum = !ignore_null_checksums || sha1.map_or(true, |sha1| sha1 != NULL_SHA1);
+                    let decryption_key = if record.encrypted() { encryption_key.as_ref() } else { None };
+                    let codec = pak.codec(record.compression_method());
+
+                    if deep && record.compression_method() != COMPR_NONE {
+                        if codec.is_none() {
+                            check_error!(ok, result_sender, abort_on_error, Error::new(format!(
+                                "unsupported compression method, can't decompress data: {}",
+                                pak.compression_method_name(record.compression_method()),
+                            )).with_path(record.filename()));
+                        } else if record.encrypted() && decryption_key.is_none() {
+                            check_error!(ok, result_sender, abort_on_error, Error::new(
+                                "data is encrypted, but no encryption key was given, can't decompress data".to_string()
+                            ).with_path(record.filename()));
+                        }
+                    }
+
+                    // Encrypted data is always decompressed if there is a key, because it's the
+                    // only way to tell whether the key is right. The hash is over the encrypted data.
+                    let mut inflate = codec.is_some() && if record.encrypted() { decryption_key.is_some() } else { deep };
+
                     if let Some(blocks) = record.compression_blocks() {
-                        if !ignore_null_checksums || record.sha1().map_or(true, |sha1| sha1 != NULL_SHA1) {
-                            let header_size = Pak::header_size(version, variant, record);
-                            let mut hasher = OpenSSLSha1::new();
-
-                            let base_offset;
-                            let mut next_start_offset;
-
-                            if variant == Variant::ConanExiles {
-                                // only version 4 is correctly supported
-                                base_offset = 0;
-                                next_start_offset = record.offset() + header_size + 20;
-                            } else if version >= 7 {
-                                // + 4 for unknown extra field in inline record
-                                base_offset = record.offset();
-                                next_start_offset = header_size + 4;
-                            } else if version >= 4 {
-                                base_offset = 0;
-                                next_start_offset = record.offset() + header_size + 4;
-                            } else {
-                                base_offset = 0;
-                                next_start_offset = record.offset() + header_size;
-                            }
+                        let header_size = Pak::header_size(version, variant, record);
+                        let mut hasher = OpenSSLSha1::new();
+
+                        let base_offset;
+                        let mut next_start_offset;
+
+                        if version >= PAK_RELATIVE_COMPRESSION_OFFSET_VERSION {
+                            base_offset = record.offset();
+                            next_start_offset = header_size;
+                        } else {
+                            base_offset = 0;
+                            next_start_offset = record.offset() + header_size;
+                        }
 
-                            let end_offset = next_start_offset + record.size();
+                        let end_offset = next_start_offset + record.size();
+                        let mut remaining = record.uncompressed_size();
+                        let mut total_uncompressed_size = 0u64;
 
-                            for (index, block) in blocks.iter().enumerate() {
-                                if block.start_offset > block.end_offset {
+                        for (index, block) in blocks.iter().enumerate() {
+                            if block.start_offset > block.end_offset {
+                                check_error!(ok, result_sender, abort_on_error,
+                                    Error::new(format!(
+                                        "compression block start offset is bigger than end offset: {} > {}",
+                                        block.start_offset, block.end_offset,
+                                    )).with_path(record.filename()));
+                            } else {
+                                if next_start_offset != block.start_offset {
                                     check_error!(ok, result_sender, abort_on_error,
                                         Error::new(format!(
-                                            "compression block start offset is bigger than end offset: {} > {}",
-                                            block.start_offset, block.end_offset,
+                                            "compression block with index {} start offset differes from expected value: {} != {} ({})",
+                                            index, block.start_offset, next_start_offset, block.start_offset as i64 - next_start_offset as i64,
                                         )).with_path(record.filename()));
+                                }
+
+                                let block_size = block.end_offset - block.start_offset;
+                                let stored_size = if record.encrypted() {
+                                    align(block_size, BLOCK_SIZE as u64)
                                 } else {
-                                    if next_start_offset != block.start_offset {
+                                    block_size
+                                };
+
+                                buffer.resize(stored_size as usize, 0);
+                                if let Err(error) = io!{
+                                    reader.seek(SeekFrom::Start(base_offset + block.start_offset)),
+                                    reader.read_exact(&mut buffer)
+                                } {
+                                    let _ = result_sender.send(Err(Error::io_with_path(error, record.filename())));
+                                    return;
+                                }
+                                hasher.update(&buffer);
+
+                                if let (true, Some(codec)) = (inflate, codec) {
+                                    if let Some(key) = decryption_key {
+                                        decrypt(&mut buffer, key);
+                                        buffer.truncate(block_size as usize);
+                                    }
+                                    let uncompressed_size = remaining.min(record.compression_block_size() as u64);
+                                    if let Err(error) = inflate_block(codec.as_ref(), &buffer, uncompressed_size as usize, &mut out_buffer) {
+                                        let message = if decryption_key.is_some() {
+                                            format!("compression block with index {} can't be decompressed after decryption, \
+                                                     the encryption key is probably wrong: {}", index, error)
+                                        } else {
+                                            format!("compression block with index {} can't be decompressed: {}", index, error)
+                                        };
                                         check_error!(ok, result_sender, abort_on_error,
-                                            Error::new(format!(
-                                                "compression block with index {} start offset differes from expected value: {} != {} ({})",
-                                                index, block.start_offset, next_start_offset, block.start_offset as i64 - next_start_offset as i64,
-                                            )).with_path(record.filename()));
+                                            Error::new(message).with_path(record.filename()));
+                                        // the following blocks are still hashed
+                                        inflate = false;
+                                    } else {
+                                        if index + 1 < blocks.len() && out_buffer.len() as u64 != record.compression_block_size() as u64 {
+                                            check_error!(ok, result_sender, abort_on_error,
+                                                Error::new(format!(
+                                                    "compression block with index {} decompresses to {} bytes, but compression block size is {}",
+                                                    index, out_buffer.len(), record.compression_block_size(),
+                                                )).with_path(record.filename()));
+                                        }
+                                        remaining = remaining.saturating_sub(out_buffer.len() as u64);
+                                        total_uncompressed_size += out_buffer.len() as u64;
                                     }
+                                }
+
+                                next_start_offset += stored_size;
+                            }
+                        }
+
+                        if next_start_offset != end_offset {
+                            check_error!(ok, result_sender, abort_on_error,
+                                Error::new(format!(
+                                    "actual record end offset differes from expected value: {} != {} ({})",
+                                    next_start_offset, end_offset, next_start_offset as i64 - end_offset as i64,
+                                )).with_path(record.filename()));
+                        }
+
+                        if inflate && total_uncompressed_size != record.uncompressed_size() {
+                            check_error!(ok, result_sender, abort_on_error,
+                                Error::new(format!(
+                                    "decompressed size differes from uncompressed size: {} != {}",
+                                    total_uncompressed_size, record.uncompressed_size(),
+                                )).with_path(record.filename()));
+                        }
+
+                        let actual_digest = hasher.finish();
+                        if verify_checksum && &actual_digest != sha1.as_ref().unwrap_or(&NULL_SHA1) {
+                            check_error!(ok, result_sender, abort_on_error, Error::new(format!(
+                                "checksum missmatch:\n\
+                                \texpected: {}\n\
+                                \tactual:   {}",
+                                HexDisplay::new(sha1.as_ref().unwrap_or(&NULL_SHA1)),
+                                HexDisplay::new(&actual_digest)
+                            )).with_path(record.filename()));
+                        }
+                    } else {
+                        let stored_size = if record.encrypted() {
+                            align(record.size(), BLOCK_SIZE as u64)
+                        } else {
+                            record.size()
+                        };
 
-                                    let block_size = block.end_offset - block.start_offset;
+                        if let Err(error) = check_data(&mut reader, record.filename(), offset,
+                                stored_size, sha1.as_ref().unwrap_or(&NULL_SHA1), ignore_null_checksums, &mut buffer) {
+                            check_error!(ok, result_sender, abort_on_error, error);
+                        }
 
-                                    buffer.resize(block_size as usize, 0);
-                                    if let Err(error) = io!{
-                                        reader.seek(SeekFrom::Start(base_offset + block.start_offset)),
-                                        reader.read_exact(&mut buffer)
-                                    } {
+                        // a key that already decrypted the index is known to be right
+                        if let Some(key) = decryption_key.filter(|_| record.compression_method() == COMPR_NONE) {
+                            if !(pak.encrypted_index() && pak.encryption_key() == Some(&key[..])) {
+                                match plausible_padding(&mut reader, offset, record.size(), key) {
+                                    Ok(true) => {}
+                                    Ok(false) => {
+                                        check_error!(ok, result_sender, abort_on_error, Error::new(
+                                            "padding of the decrypted data is neither zeros nor a repetition of the data, \
+                                             the encryption key is probably wrong".to_string()
+                                        ).with_path(record.filename()));
+                                    }
+                                    Err(error) => {
                                         let _ = result_sender.send(Err(Error::io_with_path(error, record.filename())));
                                         return;
                                     }
-                                    hasher.update(&buffer);
-
-                                    next_start_offset += block_size;
                                 }
                             }
+                        }
 
-                            if next_start_offset != end_offset {
-                                check_error!(ok, result_sender, abort_on_error,
-                                    Error::new(format!(
-                                        "actual record end offset differes from expected value: {} != {} ({})",
-                                        next_start_offset, end_offset, next_start_offset as i64 - end_offset as i64,
-                                    )).with_path(record.filename()));
+                        // version 2 has compression support, but not compression blocks
+                        if let (true, Some(codec)) = (inflate, codec) {
+                            buffer.resize(stored_size as usize, 0);
+                            if let Err(error) = io!{
+                                reader.seek(SeekFrom::Start(offset)),
+                                reader.read_exact(&mut buffer)
+                            } {
+                                let _ = result_sender.send(Err(Error::io_with_path(error, record.filename())));
+                                return;
                             }
-
-                            let actual_digest = hasher.finish();
-                            if &actual_digest != record.sha1().as_ref().unwrap_or(&NULL_SHA1) {
+                            if let Some(key) = decryption_key {
+                                decrypt(&mut buffer, key);
+                                buffer.truncate(record.size() as usize);
+                            }
+                            if let Err(error) = inflate_block(codec.as_ref(), &buffer, record.uncompressed_size() as usize, &mut out_buffer) {
+                                check_error!(ok, result_sender, abort_on_error, Error::new(format!(
+                                    "data can't be decompressed: {}", error,
+                                )).with_path(record.filename()));
+                            } else if out_buffer.len() as u64 != record.uncompressed_size() {
                                 check_error!(ok, result_sender, abort_on_error, Error::new(format!(
-                                    "checksum missmatch:\n\
-                                    \texpected: {}\n\
-                                    \tactual:   {}",
-                                    HexDisplay::new(record.sha1().as_ref().unwrap_or(&NULL_SHA1)),
-                                    HexDisplay::new(&actual_digest)
+                                    "decompressed size differes from uncompressed size: {} != {}",
+                                    out_buffer.len(), record.uncompressed_size(),
                                 )).with_path(record.filename()));
                             }
                         }
-                    } else if let Err(error) = check_data(&mut reader, record.filename(), offset,
-                            record.size(), record.sha1().as_ref().unwrap_or(&NULL_SHA1), ignore_null_checksums, &mut buffer) {
-                        check_error!(ok, result_sender, abort_on_error, error);
                     }
 
                     if ok {
@@ -321,7 +639,7 @@ pub fn check<'a>(pak: &'a Pak, in_file: &mut File, options: CheckOptions) -> Res
             error_count += enqueue(pak.index().records().iter(), work_sender, abort_on_error, null_separated)?;
         }
 
-        let linesep = if options.null_separated { '\0' } else { '\n' };
+        let linesep = if null_separated { '\0' } else { '\n' };
 
         while let Ok(result) = result_receiver.recv() {
             match result {
@@ -0,0 +1,217 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{io::{Read, Write}, sync::Arc};
+
+use flate2::{Compression, bufread::{GzDecoder, ZlibDecoder}, write::{GzEncoder, ZlibEncoder}};
+
+use crate::{Error, Result, pak::{COMPR_CUSTOM, COMPR_GZIP, COMPR_LZ4, COMPR_NAME_GZIP, COMPR_NAME_LZ4, COMPR_NAME_ZLIB, COMPR_ZLIB}};
+
+/// A block compression codec.
+///
+/// Records of paks before version 8 refer to a codec by its method id,
+/// records of version 8 and up refer to it by the name stored in the footer.
+pub trait Codec: Send + Sync {
+    /// Name as written into the footer of version 8+ paks, e.g. `"Zlib"`.
+    fn name(&self) -> &str;
+
+    /// Compression method id. Ids below [`crate::pak::COMPR_CUSTOM`] are the
+    /// values used by paks before version 8, ids starting with it can only be
+    /// used with version 8 and up.
+    fn method(&self) -> u32;
+
+    /// Compresses `input` into `output`, replacing its previous content.
+    fn compress_block(&self, input: &[u8], level: u32, output: &mut Vec<u8>) -> Result<()>;
+
+    /// Decompresses `input` into `output`, replacing its previous content.
+    /// `uncompressed_size` is the expected size of the decompressed block.
+    fn decompress_block(&self, input: &[u8], uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()>;
+
+    /// Like [`Codec::decompress_block`], but returns how many bytes of `input`
+    /// were consumed, so trailing garbage after the compressed stream can be
+    /// detected. Codecs that can't tell report the whole input as consumed.
+    fn decompress_block_consumed(&self, input: &[u8], uncompressed_size: usize, output: &mut Vec<u8>) -> Result<usize> {
+        self.decompress_block(input, uncompressed_size, output)?;
+        Ok(input.len())
+    }
+}
+
+impl std::fmt::Debug for dyn Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name(), self.method())
+    }
+}
+
+#[derive(Debug)]
+pub struct ZlibCodec;
+
+impl Codec for ZlibCodec {
+    fn name(&self) -> &str {
+        COMPR_NAME_ZLIB
+    }
+
+    fn method(&self) -> u32 {
+        COMPR_ZLIB
+    }
+
+    fn compress_block(&self, input: &[u8], level: u32, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        let mut zlib = ZlibEncoder::new(output, Compression::new(level));
+        zlib.write_all(input)?;
+        zlib.finish()?;
+        Ok(())
+    }
+
+    fn decompress_block(&self, input: &[u8], _uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        let mut zlib = ZlibDecoder::new(input);
+        zlib.read_to_end(output)?;
+        Ok(())
+    }
+
+    fn decompress_block_consumed(&self, input: &[u8], _uncompressed_size: usize, output: &mut Vec<u8>) -> Result<usize> {
+        output.clear();
+        // the bufread decoder only consumes what inflate used, so this is exact
+        let mut zlib = ZlibDecoder::new(input);
+        zlib.read_to_end(output)?;
+        Ok(zlib.total_in() as usize)
+    }
+}
+
+#[derive(Debug)]
+pub struct GzipCodec;
+
+impl Codec for GzipCodec {
+    fn name(&self) -> &str {
+        COMPR_NAME_GZIP
+    }
+
+    fn method(&self) -> u32 {
+        COMPR_GZIP
+    }
+
+    fn compress_block(&self, input: &[u8], level: u32, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        let mut gzip = GzEncoder::new(output, Compression::new(level));
+        gzip.write_all(input)?;
+        gzip.finish()?;
+        Ok(())
+    }
+
+    fn decompress_block(&self, input: &[u8], _uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        let mut gzip = GzDecoder::new(input);
+        gzip.read_to_end(output)?;
+        Ok(())
+    }
+
+    fn decompress_block_consumed(&self, input: &[u8], _uncompressed_size: usize, output: &mut Vec<u8>) -> Result<usize> {
+        output.clear();
+        // header and trailer aren't counted by total_in(), so use the rest
+        // of the slice the bufread decoder didn't consume
+        let mut gzip = GzDecoder::new(input);
+        gzip.read_to_end(output)?;
+        Ok(input.len() - gzip.into_inner().len())
+    }
+}
+
+#[derive(Debug)]
+pub struct Lz4Codec;
+
+impl Codec for Lz4Codec {
+    fn name(&self) -> &str {
+        COMPR_NAME_LZ4
+    }
+
+    fn method(&self) -> u32 {
+        COMPR_LZ4
+    }
+
+    fn compress_block(&self, input: &[u8], _level: u32, output: &mut Vec<u8>) -> Result<()> {
+        // UE uses raw LZ4 blocks without the size prefix or frame format.
+        output.clear();
+        output.resize(lz4_flex::block::get_maximum_output_size(input.len()), 0);
+        let size = match lz4_flex::block::compress_into(input, output) {
+            Ok(size) => size,
+            Err(error) => return Err(Error::new(format!("LZ4 compression error: {}", error)))
+        };
+        output.truncate(size);
+        Ok(())
+    }
+
+    fn decompress_block(&self, input: &[u8], uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        // raw LZ4 block, the uncompressed size is only known from the record
+        output.clear();
+        output.resize(uncompressed_size, 0);
+        let size = match lz4_flex::block::decompress_into(input, output) {
+            Ok(size) => size,
+            Err(error) => return Err(Error::new(format!("LZ4 decompression error: {}", error)))
+        };
+        output.truncate(size);
+        Ok(())
+    }
+}
+
+/// Set of codecs that can be looked up by method id or footer name.
+///
+/// The default registry contains the built-in Zlib, Gzip, and LZ4 codecs.
+/// Codecs registered later take precedence over earlier ones with the same
+/// method id or name, so built-in codecs can be replaced.
+#[derive(Clone)]
+pub struct CodecRegistry {
+    codecs: Vec<Arc<dyn Codec>>,
+}
+
+impl CodecRegistry {
+    /// Creates a registry without any codecs.
+    pub fn new() -> Self {
+        Self { codecs: Vec::new() }
+    }
+
+    pub fn register(&mut self, codec: impl Codec + 'static) {
+        self.codecs.push(Arc::new(codec));
+    }
+
+    #[inline]
+    pub fn codecs(&self) -> &[Arc<dyn Codec>] {
+        &self.codecs
+    }
+
+    pub fn by_method(&self, method: u32) -> Option<&Arc<dyn Codec>> {
+        self.codecs.iter().rev().find(|codec| codec.method() == method)
+    }
+
+    /// Footer names are compared case-insensitively.
+    pub fn by_name(&self, name: &str) -> Option<&Arc<dyn Codec>> {
+        self.codecs.iter().rev().find(|codec| codec.name().eq_ignore_ascii_case(name))
+    }
+
+    /// A custom compression method id that isn't used by any registered codec.
+    pub fn unused_custom_method(&self) -> u32 {
+        self.codecs.iter()
+            .map(|codec| codec.method())
+            .filter(|&method| method >= COMPR_CUSTOM)
+            .max()
+            .map(|method| method + 1)
+            .unwrap_or(COMPR_CUSTOM)
+    }
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        let mut codecs = Self::new();
+        codecs.register(ZlibCodec);
+        codecs.register(GzipCodec);
+        codecs.register(Lz4Codec);
+        codecs
+    }
+}
+
+impl std::fmt::Debug for CodecRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.codecs.iter()).finish()
+    }
+}
@@ -0,0 +1,181 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Files that are in more than one of several paks, e.g. files of the base
+//! game that are overridden by mods. Copies are compared by the SHA-1 of
+//! their record, i.e. of the data as it is stored in the pak.
+
+use std::collections::BTreeMap;
+use std::io::{Seek, SeekFrom};
+
+use crate::{Error, Pak, ReadAt, ReadAtCursor, Record, Result};
+use crate::check::NULL_SHA1;
+use crate::pak::{Sha1, Variant, PAK_PATH_HASH_INDEX_VERSION};
+use crate::pakset::{join_path, mount_point_components};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyState {
+    /// The copy of the last pak, which is the one that is used.
+    Winner,
+    /// Same SHA-1 as the winner.
+    Identical,
+    /// Different SHA-1 than the winner.
+    Different,
+    /// The copy or the winner has no SHA-1.
+    Unknown,
+}
+
+impl CopyState {
+    pub fn name(self) -> &'static str {
+        match self {
+            CopyState::Winner    => "wins",
+            CopyState::Identical => "identical",
+            CopyState::Different => "differs",
+            CopyState::Unknown   => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConflictCopy<'a> {
+    pak_index: usize,
+    record: &'a Record,
+    sha1: Option<Sha1>,
+}
+
+impl<'a> ConflictCopy<'a> {
+    /// Index of the pak in the slice passed to [`conflicts`].
+    #[inline]
+    pub fn pak_index(&self) -> usize {
+        self.pak_index
+    }
+
+    #[inline]
+    pub fn record(&self) -> &'a Record {
+        self.record
+    }
+
+    /// SHA-1 of the record, or of its inline header if the index has none
+    /// (version 10 and up). `None` if it is missing or all zero.
+    #[inline]
+    pub fn sha1(&self) -> Option<&Sha1> {
+        self.sha1.as_ref()
+    }
+}
+
+#[derive(Debug)]
+pub struct Conflict<'a> {
+    path: String,
+    // in load order
+    copies: Vec<ConflictCopy<'a>>,
+}
+
+impl<'a> Conflict<'a> {
+    /// Mount point and filename of the records joined, like in a
+    /// [`crate::PakSet`].
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// All copies in load order.
+    #[inline]
+    pub fn copies(&self) -> &[ConflictCopy<'a>] {
+        &self.copies
+    }
+
+    #[inline]
+    pub fn winner(&self) -> &ConflictCopy<'a> {
+        &self.copies[self.copies.len() - 1]
+    }
+
+    /// State of the copy at `index` of [`Conflict::copies`].
+    pub fn state(&self, index: usize) -> CopyState {
+        if index + 1 == self.copies.len() {
+            return CopyState::Winner;
+        }
+
+        match (self.copies[index].sha1(), self.winner().sha1()) {
+            (Some(sha1), Some(winner_sha1)) if sha1 == winner_sha1 => CopyState::Identical,
+            (Some(_), Some(_)) => CopyState::Different,
+            _ => CopyState::Unknown,
+        }
+    }
+
+    /// Whether all copies are identical, or `None` if some can't be compared.
+    pub fn identical(&self) -> Option<bool> {
+        let mut identical = Some(true);
+        for index in 0..self.copies.len() - 1 {
+            match self.state(index) {
+                CopyState::Different => return Some(false),
+                CopyState::Unknown => identical = None,
+                _ => {}
+            }
+        }
+        identical
+    }
+}
+
+/// All paths that are in more than one of `paks`, sorted by path. The paks
+/// are in load order, so the copy of the last pak wins. `readers` are the
+/// sources of the paks and are only used to read the SHA-1 of conflicting
+/// records that aren't in the index.
+pub fn conflicts<'a, R>(paks: &'a [Pak], readers: &[R]) -> Result<Vec<Conflict<'a>>>
+where R: ReadAt {
+    if paks.len() != readers.len() {
+        return Err(Error::new(format!("got {} paks, but {} readers", paks.len(), readers.len())));
+    }
+
+    let mut files: BTreeMap<String, Vec<(usize, &Record)>> = BTreeMap::new();
+
+    for (pak_index, pak) in paks.iter().enumerate() {
+        let mount_point = mount_point_components(pak.index().mount_point());
+        for record in pak.index().records() {
+            files.entry(join_path(&mount_point, record.filename()))
+                .or_default()
+                .push((pak_index, record));
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for (path, records) in files {
+        if records.len() < 2 {
+            continue;
+        }
+
+        let mut copies = Vec::with_capacity(records.len());
+        for (pak_index, record) in records {
+            let sha1 = match record.sha1() {
+                Some(sha1) => Some(*sha1),
+                None => read_inline_sha1(&paks[pak_index], &readers[pak_index], record)?,
+            };
+
+            copies.push(ConflictCopy {
+                pak_index,
+                record,
+                sha1: sha1.filter(|sha1| sha1 != &NULL_SHA1),
+            });
+        }
+
+        conflicts.push(Conflict { path, copies });
+    }
+
+    Ok(conflicts)
+}
+
+// the inline record header of version 10 and up is still the full v3 header
+fn read_inline_sha1(pak: &Pak, reader: &impl ReadAt, record: &Record) -> Result<Option<Sha1>> {
+    if pak.variant() != Variant::Standard || pak.version() < PAK_PATH_HASH_INDEX_VERSION {
+        return Ok(None);
+    }
+
+    let mut reader = ReadAtCursor::new(reader);
+    reader.seek(SeekFrom::Start(record.offset()))?;
+    let inline_record = Record::read_v3(&mut reader, record.filename().to_string())
+        .map_err(|error| error.with_path_if_none(record.filename()))?;
+
+    Ok(*inline_record.sha1())
+}
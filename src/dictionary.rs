@@ -0,0 +1,147 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Candidate paths used to restore file names of paks that only have a path
+//! hash index (version 10 and up).
+//!
+//! A dictionary file is a text file with one path per line. Empty lines and
+//! lines starting with `#` are ignored. Paths are relative to the mount point
+//! of the pak, but paths that start with the mount point are accepted too.
+//!
+//! Such a file can be generated from paks that do have a full directory index
+//! via [`Dictionary::insert_pak`] and [`Dictionary::write_to`].
+
+use std::{collections::{BTreeSet, HashMap}, io::{Read, Seek, Write}, path::Path};
+
+use crate::{Error, Pak, Result, index::{path_hash, read_path_hash_index}};
+
+/// How many entries of the path hash index of a pak are found in a dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Coverage {
+    pub path_hash_count: usize,
+    pub resolved_count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    paths: BTreeSet<String>,
+}
+
+impl Dictionary {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(data) => Ok(Self::from_text(&data)),
+            Err(error) => Err(Error::io_with_path(error, path)),
+        }
+    }
+
+    pub fn from_text(data: &str) -> Self {
+        let mut dictionary = Self::new();
+        for line in data.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                dictionary.insert(line);
+            }
+        }
+        dictionary
+    }
+
+    /// Adds a path. Backslashes are replaced by slashes and leading slashes
+    /// are removed.
+    pub fn insert(&mut self, path: &str) {
+        let path = normalize_path(path);
+        if !path.is_empty() {
+            self.paths.insert(path);
+        }
+    }
+
+    /// Adds the paths of all records of a pak, each also in its lower case
+    /// form. Fails for paks that only have a path hash index, since their
+    /// paths are unknown.
+    pub fn insert_pak(&mut self, pak: &Pak) -> Result<()> {
+        if let Some(info) = pak.index().secondary_index() {
+            if !info.has_full_directory_index() {
+                return Err(Error::new(
+                    "pak has no full directory index, paths can't be read from it".to_string()));
+            }
+        }
+
+        for record in pak.index().records() {
+            self.insert(record.filename());
+            self.insert(&record.filename().to_lowercase());
+        }
+
+        Ok(())
+    }
+
+    /// Writes the paths one per line in sorted order.
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        for path in &self.paths {
+            writeln!(writer, "{}", path)?;
+        }
+        Ok(())
+    }
+
+    /// Counts the entries of the path hash index of a pak that are found in
+    /// this dictionary. `reader` has to read the same file as `pak`.
+    pub fn coverage<R>(&self, pak: &Pak, reader: &mut R) -> Result<Coverage>
+    where R: Read, R: Seek {
+        let info = match pak.index().secondary_index() {
+            Some(info) if info.has_path_hash_index() => info,
+            _ => return Err(Error::new("pak has no path hash index".to_string())),
+        };
+
+        let data = pak.read_index_data(reader, info.path_hash_index_offset(), info.path_hash_index_size())?;
+        let entries = read_path_hash_index(&data).map_err(|error| error.with_path("<path hash index>"))?;
+        let hashes = self.hashes(info.path_hash_seed(), pak.version(), pak.index().mount_point());
+
+        Ok(Coverage {
+            path_hash_count: entries.len(),
+            resolved_count: entries.iter().filter(|(hash, _)| hashes.contains_key(hash)).count(),
+        })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// The normalized paths in sorted order.
+    #[inline]
+    pub fn paths(&self) -> impl Iterator<Item=&str> + '_ {
+        self.paths.iter().map(String::as_str)
+    }
+
+    /// Maps the path hashes as used by a pak with the given path hash seed,
+    /// version, and mount point to the paths of this dictionary.
+    pub fn hashes(&self, seed: u64, version: u32, mount_point: Option<&str>) -> HashMap<u64, &str> {
+        let mount_point = mount_point.map(normalize_path).filter(|mount_point| !mount_point.is_empty());
+        let mut hashes = HashMap::with_capacity(self.paths.len());
+        for path in &self.paths {
+            let path = match &mount_point {
+                Some(mount_point) => path.strip_prefix(mount_point.as_str()).unwrap_or(path),
+                None => path,
+            };
+            hashes.entry(path_hash(path, seed, version)).or_insert(path);
+        }
+        hashes
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/").trim_start_matches('/').to_string()
+}
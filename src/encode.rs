@@ -36,6 +36,14 @@ impl Encode for u64 {
     }
 }
 
+impl Encode for u128 {
+    #[inline]
+    fn encode(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(&self.to_le_bytes())?;
+        Ok(())
+    }
+}
+
 impl<const N: usize> Encode for [u8; N] {
     #[inline]
     fn encode(&self, writer: &mut impl Write) -> Result<()> {
@@ -0,0 +1,34 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use aes::cipher::{BlockEncrypt, NewBlockCipher};
+use aes::{Aes256, Block, BLOCK_SIZE};
+use log::trace;
+
+use crate::{Error, Result};
+use crate::util::align;
+
+pub fn encrypt(data: &mut [u8], key: &[u8]) -> Result<()> {
+    trace!("Encrypting data using aes256 with key {:?}", key);
+    let cipher = match Aes256::new_from_slice(key) {
+        Ok(cipher) => cipher,
+        Err(_) => return Err(Error::new(format!(
+            "encryption key needs to be 32 bytes long, but was {} bytes", key.len())))
+    };
+    assert_eq!(data.len() % BLOCK_SIZE, 0, "Data length must be a multiple of 16");
+
+    for block in data.chunks_mut(BLOCK_SIZE) {
+        cipher.encrypt_block(Block::from_mut_slice(block));
+    }
+
+    Ok(())
+}
+
+/// Pads data with zeros to a multiple of 16 bytes and encrypts it.
+pub fn pad_and_encrypt(data: &mut Vec<u8>, key: &[u8]) -> Result<()> {
+    data.resize(align(data.len() as u64, BLOCK_SIZE as u64) as usize, 0);
+    encrypt(data, key)
+}
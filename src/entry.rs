@@ -0,0 +1,274 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::borrow::Cow;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+use aes::BLOCK_SIZE;
+
+use crate::codec::Codec;
+use crate::decrypt::decrypt;
+use crate::pak::{COMPR_NONE, PAK_RELATIVE_COMPRESSION_OFFSET_VERSION};
+use crate::record::CompressionBlock;
+use crate::util::align;
+use crate::{Error, Pak, Record, Result};
+
+/// Where the data of a record is stored. Offsets are absolute.
+#[derive(Debug, Clone)]
+pub(crate) struct EntryLayout {
+    pub(crate) encrypted: bool,
+    pub(crate) data_offset: u64,
+    pub(crate) uncompressed_size: u64,
+    // records without compression blocks are a single block
+    pub(crate) compression_blocks: Vec<CompressionBlock>,
+    pub(crate) compression_block_size: u64,
+}
+
+impl EntryLayout {
+    pub(crate) fn new(pak: &Pak, record: &Record) -> Self {
+        let data_offset = record.offset() + Pak::header_size(pak.version(), pak.variant(), record);
+        let uncompressed_size = record.uncompressed_size();
+
+        let (compression_blocks, compression_block_size) = match record.compression_blocks() {
+            Some(blocks) => {
+                let base_offset = if pak.version() < PAK_RELATIVE_COMPRESSION_OFFSET_VERSION {
+                    0
+                } else {
+                    record.offset()
+                };
+                let blocks = blocks.iter().map(|block| CompressionBlock {
+                    start_offset: base_offset + block.start_offset,
+                    end_offset:   base_offset + block.end_offset,
+                }).collect();
+                let block_size = record.compression_block_size() as u64;
+                (blocks, if block_size == 0 { uncompressed_size } else { block_size })
+            }
+            // version 2 has compression support, but not compression blocks
+            None => (vec![CompressionBlock {
+                start_offset: data_offset,
+                end_offset:   data_offset + record.size(),
+            }], uncompressed_size),
+        };
+
+        Self {
+            encrypted: record.encrypted(),
+            data_offset,
+            uncompressed_size,
+            compression_blocks,
+            compression_block_size,
+        }
+    }
+}
+
+/// Reads the uncompressed (and decrypted) data of a record.
+///
+/// Compressed data is decompressed one compression block at a time, so
+/// seeking only needs to decompress the block that contains the new
+/// position. Created by [`Pak::open_entry`].
+pub struct EntryReader<'a, R> {
+    reader: R,
+    codec: Option<&'a dyn Codec>,
+    encryption_key: Option<Vec<u8>>,
+    layout: Cow<'a, EntryLayout>,
+    position: u64,
+    // index and decompressed data of the last read compression block
+    block_index: Option<usize>,
+    block_buffer: Vec<u8>,
+    in_buffer: Vec<u8>,
+}
+
+impl<'a, R> EntryReader<'a, R>
+where R: Read, R: Seek {
+    #[inline]
+    pub fn new(pak: &'a Pak, reader: R, record: &Record) -> Result<Self> {
+        Self::with_key(pak, reader, record, pak.encryption_key())
+    }
+
+    /// Like [`EntryReader::new`], but decrypts with `encryption_key` instead
+    /// of the key of the pak.
+    pub fn with_key(pak: &'a Pak, reader: R, record: &Record, encryption_key: Option<&[u8]>) -> Result<Self> {
+        let codec = if record.compression_method() == COMPR_NONE {
+            None
+        } else if let Some(codec) = pak.codec(record.compression_method()) {
+            Some(codec.as_ref())
+        } else {
+            return Err(Error::new(format!(
+                    "unsupported compression method: {}",
+                    pak.compression_method_name(record.compression_method())))
+                .with_path(record.filename()));
+        };
+
+        if record.encrypted() && encryption_key.is_none() {
+            return Err(Error::new(
+                    "file is encrypted, but no encryption key was given".to_string())
+                .with_path(record.filename()));
+        }
+
+        Ok(Self::from_layout(reader, Cow::Owned(EntryLayout::new(pak, record)), codec, encryption_key))
+    }
+
+    pub(crate) fn from_layout(reader: R, layout: Cow<'a, EntryLayout>, codec: Option<&'a dyn Codec>, encryption_key: Option<&[u8]>) -> Self {
+        Self {
+            reader,
+            codec,
+            encryption_key: encryption_key.map(<[u8]>::to_vec),
+            layout,
+            position: 0,
+            block_index: None,
+            block_buffer: Vec::new(),
+            in_buffer: Vec::new(),
+        }
+    }
+
+    /// Size of the uncompressed data.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.layout.uncompressed_size
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.layout.uncompressed_size == 0
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Reads raw data into in_buffer. Encrypted data is read in whole AES blocks
+    // (relative to the start of the data) and then decrypted.
+    fn read_raw(&mut self, offset: u64, size: u64) -> std::io::Result<()> {
+        if !self.layout.encrypted {
+            self.in_buffer.resize(size as usize, 0);
+            self.reader.seek(SeekFrom::Start(offset))?;
+            return self.reader.read_exact(&mut self.in_buffer);
+        }
+
+        let key = match &self.encryption_key {
+            Some(key) => key,
+            None => return Err(std::io::Error::new(ErrorKind::PermissionDenied,
+                "file is encrypted, but no encryption key was given")),
+        };
+
+        let block_size = BLOCK_SIZE as u64;
+        let data_offset = self.layout.data_offset;
+        // a broken compression block might start before the data of the record
+        let relative_offset = match offset.checked_sub(data_offset) {
+            Some(relative_offset) => relative_offset,
+            None => return Err(std::io::Error::new(ErrorKind::InvalidData, format!(
+                "compression block start offset is before the start of the data: {} < {}",
+                offset, data_offset))),
+        };
+        let read_offset = data_offset + relative_offset / block_size * block_size;
+        let read_end = data_offset + align(relative_offset + size, block_size);
+
+        self.in_buffer.resize((read_end - read_offset) as usize, 0);
+        self.reader.seek(SeekFrom::Start(read_offset))?;
+        self.reader.read_exact(&mut self.in_buffer)?;
+
+        decrypt(&mut self.in_buffer, key);
+        let start = (offset - read_offset) as usize;
+        self.in_buffer.truncate(start + size as usize);
+        self.in_buffer.drain(..start);
+
+        Ok(())
+    }
+
+    fn load_block(&mut self, index: usize, codec: &dyn Codec) -> std::io::Result<()> {
+        if self.block_index == Some(index) {
+            return Ok(());
+        }
+
+        let block = match self.layout.compression_blocks.get(index) {
+            Some(block) => block.clone(),
+            None => return Err(std::io::Error::new(ErrorKind::InvalidData, format!(
+                "compression block with index {} is missing", index))),
+        };
+
+        if block.start_offset > block.end_offset {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, format!(
+                "compression block start offset is bigger than end offset: {} > {}",
+                block.start_offset, block.end_offset)));
+        }
+
+        // so a failed read doesn't leave a block buffer that doesn't match the index
+        self.block_index = None;
+        self.read_raw(block.start_offset, block.end_offset - block.start_offset)?;
+
+        let block_start = self.layout.compression_block_size * index as u64;
+        let remaining = match self.layout.uncompressed_size.checked_sub(block_start) {
+            Some(remaining) if remaining > 0 => remaining,
+            _ => return Err(std::io::Error::new(ErrorKind::InvalidData, format!(
+                "compression block with index {} starts after the end of the data", index))),
+        };
+        let uncompressed_size = self.layout.compression_block_size.min(remaining);
+        codec.decompress_block(&self.in_buffer, uncompressed_size as usize, &mut self.block_buffer)?;
+        self.block_index = Some(index);
+
+        Ok(())
+    }
+}
+
+impl<'a, R> Read for EntryReader<'a, R>
+where R: Read, R: Seek {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.layout.uncompressed_size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let remaining = self.layout.uncompressed_size - self.position;
+        let count = if let Some(codec) = self.codec {
+            let index = (self.position / self.layout.compression_block_size) as usize;
+            self.load_block(index, codec)?;
+
+            let block_offset = (self.position - self.layout.compression_block_size * index as u64) as usize;
+            if block_offset >= self.block_buffer.len() {
+                return Err(std::io::Error::new(ErrorKind::UnexpectedEof, format!(
+                    "compression block with index {} decompresses to only {} bytes",
+                    index, self.block_buffer.len())));
+            }
+
+            let count = buf.len()
+                .min(self.block_buffer.len() - block_offset)
+                .min(remaining as usize);
+            buf[..count].copy_from_slice(&self.block_buffer[block_offset..block_offset + count]);
+            count
+        } else {
+            let count = (buf.len() as u64).min(remaining) as usize;
+            if self.layout.encrypted {
+                self.read_raw(self.layout.data_offset + self.position, count as u64)?;
+                buf[..count].copy_from_slice(&self.in_buffer);
+            } else {
+                self.reader.seek(SeekFrom::Start(self.layout.data_offset + self.position))?;
+                self.reader.read_exact(&mut buf[..count])?;
+            }
+            count
+        };
+
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<'a, R> Seek for EntryReader<'a, R>
+where R: Read, R: Seek {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset)   => offset as i128,
+            SeekFrom::End(offset)     => self.layout.uncompressed_size as i128 + offset as i128,
+            SeekFrom::Current(offset) => self.position as i128 + offset as i128,
+        };
+
+        if position < 0 || position > u64::MAX as i128 {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position"));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
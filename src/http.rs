@@ -0,0 +1,363 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Reading paks from HTTP servers via range requests.
+//!
+//! Only the blocks that are actually read are downloaded. Downloaded blocks
+//! are kept in a small in-memory cache and connections are kept alive and
+//! reused, so reading the footer, the index, and then a few files only
+//! needs a handful of requests.
+
+use std::{collections::HashMap, io::{ErrorKind, Read}, num::NonZeroU64, sync::{Arc, Mutex, MutexGuard, atomic::{AtomicU64, Ordering}}, time::Duration};
+
+use ureq::{Agent, AgentBuilder, Request, Response};
+
+use crate::{Error, ReadAt, Result};
+
+pub const DEFAULT_HTTP_BLOCK_SIZE: NonZeroU64 = unsafe { NonZeroU64::new_unchecked(1024 * 1024) };
+pub const DEFAULT_HTTP_CACHE_SIZE: usize = 64;
+
+const MAX_REDIRECTS: u32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    /// Data is requested and cached in blocks of this size.
+    pub block_size: NonZeroU64,
+    /// Maximum number of cached blocks.
+    pub cache_size: usize,
+    /// Timeout for connecting, reading, and writing.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            block_size: DEFAULT_HTTP_BLOCK_SIZE,
+            cache_size: DEFAULT_HTTP_CACHE_SIZE,
+            timeout: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Returns `true` for `http://` and `https://` URLs.
+pub fn is_http_url(path: &str) -> bool {
+    strip_prefix_ignore_case(path, "http://").is_some() ||
+    strip_prefix_ignore_case(path, "https://").is_some()
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    if value.len() >= prefix.len() && value.is_char_boundary(prefix.len()) &&
+       value[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        Some(&value[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+// bytes 0-499/1234, bytes 0-499/*, or bytes */1234
+fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let value = strip_prefix_ignore_case(value, "bytes")?.trim_start();
+    let index = value.find('/')?;
+    let (range, total) = (&value[..index], &value[index + 1..]);
+    let total = if total == "*" { None } else { Some(total.parse().ok()?) };
+
+    if range == "*" {
+        let total = total?;
+        return Some((total, total, Some(total)));
+    }
+
+    let index = range.find('-')?;
+    let start: u64 = range[..index].parse().ok()?;
+    let end: u64 = range[index + 1..].parse().ok()?;
+    if end < start {
+        return None;
+    }
+
+    Some((start, end + 1, total))
+}
+
+// Least recently used blocks are dropped first.
+#[derive(Debug, Default)]
+struct BlockCache {
+    blocks: HashMap<u64, (Arc<Vec<u8>>, u64)>,
+    clock: u64,
+}
+
+impl BlockCache {
+    fn get(&mut self, index: u64) -> Option<Arc<Vec<u8>>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.blocks.get_mut(&index).map(|(block, last_used)| {
+            *last_used = clock;
+            block.clone()
+        })
+    }
+
+    #[inline]
+    fn contains(&self, index: u64) -> bool {
+        self.blocks.contains_key(&index)
+    }
+
+    fn insert(&mut self, index: u64, block: Arc<Vec<u8>>, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+
+        self.clock += 1;
+        self.blocks.insert(index, (block, self.clock));
+
+        while self.blocks.len() > capacity {
+            let oldest = self.blocks.iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(index, _)| *index);
+            if let Some(oldest) = oldest {
+                self.blocks.remove(&oldest);
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(error) => error.into_inner(),
+    }
+}
+
+/// A [`ReadAt`] that downloads the parts of a file that are read via HTTP
+/// range requests. Use it with [`crate::ReadAtCursor`] to read the pak with
+/// [`crate::Pak::from_reader`] and then pass it to e.g. [`crate::unpack::unpack`].
+pub struct HttpReader {
+    url: String,
+    size: u64,
+    options: HttpOptions,
+    agent: Agent,
+    cache: Mutex<BlockCache>,
+    request_count: AtomicU64,
+    downloaded_size: AtomicU64,
+}
+
+impl std::fmt::Debug for HttpReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpReader")
+            .field("url", &self.url)
+            .field("size", &self.size)
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+impl HttpReader {
+    /// Requests the first block to get the size of the file. Redirects
+    /// are followed.
+    pub fn open(url: &str, options: HttpOptions) -> Result<Self> {
+        if !is_http_url(url) {
+            return Err(Error::new(format!("not an http:// or https:// URL: {:?}", url)));
+        }
+
+        let mut reader = Self {
+            url: url.to_string(),
+            size: 0,
+            agent: make_agent(&options).map_err(|error| error.with_path(url))?,
+            options,
+            cache: Mutex::new(BlockCache::default()),
+            request_count: AtomicU64::new(0),
+            downloaded_size: AtomicU64::new(0),
+        };
+
+        let response = match reader.request(0, reader.options.block_size.get()).call() {
+            Ok(response) => response,
+            // range not satisfiable, i.e. an empty file
+            Err(ureq::Error::Status(416, response)) => {
+                return match content_range(&response) {
+                    Some((_, _, Some(0))) => {
+                        reader.url = response.get_url().to_string();
+                        Ok(reader)
+                    }
+                    _ => Err(Error::new("HTTP 416 Range Not Satisfiable".to_string()).with_path(url)),
+                };
+            }
+            Err(error) => return Err(Error::new(error.to_string()).with_path(url)),
+        };
+
+        if response.status() != 206 {
+            return Err(Error::new(
+                "server doesn't support range requests".to_string()).with_path(url));
+        }
+
+        reader.url = response.get_url().to_string();
+        let (end, size) = match content_range(&response) {
+            Some((0, end, Some(size))) if end == size.min(reader.options.block_size.get()) => (end, size),
+            range => return Err(Error::new(format!(
+                "illegal Content-Range in response: {:?}", range))
+                .with_path(url)),
+        };
+
+        let body = reader.read_body(response, 0, end).map_err(|error| Error::io_with_path(error, url))?;
+        reader.size = size;
+        lock(&reader.cache).insert(0, Arc::new(body), reader.options.cache_size);
+        Ok(reader)
+    }
+
+    /// The URL after following redirects.
+    #[inline]
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    #[inline]
+    pub fn options(&self) -> &HttpOptions {
+        &self.options
+    }
+
+    /// Number of range requests sent so far.
+    #[inline]
+    pub fn request_count(&self) -> u64 {
+        self.request_count.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes downloaded so far (without HTTP headers).
+    #[inline]
+    pub fn downloaded_size(&self) -> u64 {
+        self.downloaded_size.load(Ordering::Relaxed)
+    }
+
+    // Requests the bytes from start to end (exclusive).
+    fn request(&self, start: u64, end: u64) -> Request {
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        self.agent.get(&self.url)
+            .set("Range", &format!("bytes={}-{}", start, end - 1))
+    }
+
+    // The size of the body is checked before anything is allocated, so a
+    // server can't make us allocate more than what was requested.
+    fn read_body(&self, response: Response, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+        let size = end - start;
+        if let Some(length) = response.header("Content-Length") {
+            if length.parse::<u64>().ok() != Some(size) {
+                return Err(std::io::Error::new(ErrorKind::InvalidData, format!(
+                    "{}: requested {} bytes, but got Content-Length {}", self.url, size, length)));
+            }
+        }
+
+        let mut body = Vec::with_capacity(size as usize);
+        response.into_reader().take(size + 1).read_to_end(&mut body)?;
+        if body.len() as u64 != size {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, format!(
+                "{}: requested {} bytes, but got {} bytes", self.url, size, body.len())));
+        }
+        self.downloaded_size.fetch_add(size, Ordering::Relaxed);
+
+        Ok(body)
+    }
+
+    fn fetch(&self, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+        let response = match self.request(start, end).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, response)) => return Err(std::io::Error::new(ErrorKind::Other, format!(
+                "{}: HTTP {} {}", self.url, status, response.status_text()))),
+            Err(error) => return Err(std::io::Error::new(ErrorKind::Other, format!("{}: {}", self.url, error))),
+        };
+
+        if response.status() != 206 {
+            return Err(std::io::Error::new(ErrorKind::Other, format!(
+                "{}: HTTP {} {}", self.url, response.status(), response.status_text())));
+        }
+
+        let range = content_range(&response);
+        if range != Some((start, end, Some(self.size))) {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, format!(
+                "{}: requested bytes {}-{}, but got Content-Range {:?} (did the file change?)",
+                self.url, start, end - 1, range)));
+        }
+
+        self.read_body(response, start, end)
+    }
+}
+
+impl ReadAt for HttpReader {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        if offset >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let end = self.size.min(offset + buf.len() as u64);
+        let block_size = self.options.block_size.get();
+        let first_index = offset / block_size;
+        let last_index = (end - 1) / block_size;
+
+        let mut blocks = Vec::with_capacity((last_index - first_index + 1) as usize);
+        let mut index = first_index;
+        while index <= last_index {
+            if let Some(block) = lock(&self.cache).get(index) {
+                blocks.push(block);
+                index += 1;
+                continue;
+            }
+
+            // consecutive missing blocks are requested at once
+            let mut end_index = index + 1;
+            {
+                let cache = lock(&self.cache);
+                while end_index <= last_index && !cache.contains(end_index) {
+                    end_index += 1;
+                }
+            }
+
+            let data = self.fetch(index * block_size, self.size.min(end_index * block_size))?;
+            let mut cache = lock(&self.cache);
+            for (block_index, chunk) in (index..end_index).zip(data.chunks(block_size as usize)) {
+                let block = Arc::new(chunk.to_vec());
+                cache.insert(block_index, block.clone(), self.options.cache_size);
+                blocks.push(block);
+            }
+            index = end_index;
+        }
+
+        let mut position = offset;
+        for (block_index, block) in (first_index..).zip(&blocks) {
+            let block_offset = (position - block_index * block_size) as usize;
+            let count = ((block.len() - block_offset) as u64).min(end - position) as usize;
+            let buf_offset = (position - offset) as usize;
+            buf[buf_offset..buf_offset + count].copy_from_slice(&block[block_offset..block_offset + count]);
+            position += count as u64;
+        }
+
+        Ok((end - offset) as usize)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.size)
+    }
+}
+
+#[inline]
+fn content_range(response: &Response) -> Option<(u64, u64, Option<u64>)> {
+    response.header("Content-Range").and_then(parse_content_range)
+}
+
+fn make_agent(options: &HttpOptions) -> Result<Agent> {
+    // native-tls uses the same (vendored) OpenSSL and finds the system certificates
+    let connector = match native_tls::TlsConnector::new() {
+        Ok(connector) => connector,
+        Err(error) => return Err(Error::new(format!("error initializing TLS: {}", error))),
+    };
+
+    let mut builder = AgentBuilder::new()
+        .redirects(MAX_REDIRECTS)
+        .user_agent(&format!("u4pak/{}", env!("CARGO_PKG_VERSION")))
+        .tls_connector(Arc::new(connector));
+
+    if let Some(timeout) = options.timeout {
+        builder = builder
+            .timeout_connect(timeout)
+            .timeout_read(timeout)
+            .timeout_write(timeout);
+    }
+
+    Ok(builder.build())
+}
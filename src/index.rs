@@ -7,7 +7,9 @@
 use crate::decode;
 use crate::decode::Decode;
 use crate::decrypt::decrypt;
+use crate::dictionary::Dictionary;
 use crate::Variant;
+use crate::pak::{PAK_FNV64_BUGFIX_VERSION, Sha1};
 use crate::{Error, Record, Result};
 
 use std::convert::TryFrom;
@@ -76,19 +78,71 @@ pub struct IndexLoadParams {
 
 #[derive(Debug, Default)]
 pub struct SecondaryIndexInfo {
+    path_hash_seed: u64,
     has_path_hash_index: bool,
     path_hash_index_offset: i64,
     path_hash_index_size: i64,
+    path_hash_index_hash: Sha1,
     has_full_directory_index: bool,
     full_directory_index_offset: i64,
     full_directory_index_size: i64,
+    full_directory_index_hash: Sha1,
     encoded_record_info: Vec<u8>,
 }
 
+impl SecondaryIndexInfo {
+    #[inline]
+    pub fn path_hash_seed(&self) -> u64 {
+        self.path_hash_seed
+    }
+
+    #[inline]
+    pub fn has_path_hash_index(&self) -> bool {
+        self.has_path_hash_index
+    }
+
+    #[inline]
+    pub fn path_hash_index_offset(&self) -> u64 {
+        self.path_hash_index_offset as u64
+    }
+
+    #[inline]
+    pub fn path_hash_index_size(&self) -> u64 {
+        self.path_hash_index_size as u64
+    }
+
+    #[inline]
+    pub fn path_hash_index_hash(&self) -> &Sha1 {
+        &self.path_hash_index_hash
+    }
+
+    #[inline]
+    pub fn has_full_directory_index(&self) -> bool {
+        self.has_full_directory_index
+    }
+
+    #[inline]
+    pub fn full_directory_index_offset(&self) -> u64 {
+        self.full_directory_index_offset as u64
+    }
+
+    #[inline]
+    pub fn full_directory_index_size(&self) -> u64 {
+        self.full_directory_index_size as u64
+    }
+
+    #[inline]
+    pub fn full_directory_index_hash(&self) -> &Sha1 {
+        &self.full_directory_index_hash
+    }
+}
+
 #[derive(Debug)]
 pub struct Index {
     mount_point: Option<String>,
     records: Vec<Record>,
+    // only version 10 and up
+    secondary_index: Option<SecondaryIndexInfo>,
 }
 
 impl Index {
@@ -96,6 +150,7 @@ impl Index {
         Self {
             mount_point,
             records,
+            secondary_index: None,
         }
     }
     pub fn read<R>(
@@ -105,6 +160,7 @@ impl Index {
         variant: Variant,
         encoding: Encoding,
         encryption_key: Option<Vec<u8>>,
+        dictionary: &Dictionary,
     ) -> Result<Self> 
     where
         R: Read,
@@ -120,16 +176,19 @@ impl Index {
 
         let mount_point = read_path(decrypted_index, encoding)?;
         let records;
+        let mut secondary_index = None;
         if version < 10 {
             records = read_records_legacy(decrypted_index, version, variant, encoding)
                 .expect("Failed to read index records");
         } else {
             if let Ok((index_info, mut r)) = read_records(decrypted_index, encoding) {
-                if let Ok(mut sec_records) = read_secondary_index_records(reader, &index_info, encryption_key, encoding) {
+                let mount_point = if mount_point.is_empty() { None } else { Some(mount_point.as_str()) };
+                if let Ok(mut sec_records) = read_secondary_index_records(reader, &index_info, encryption_key, encoding, version, mount_point, dictionary) {
                     r.append(&mut sec_records);
                 }
 
                 records = r;
+                secondary_index = Some(index_info);
             } else {
                 return Err(Error::new(format!(
                     "Only know how to handle Conan Exile paks of version 4, but version was {}.",
@@ -141,6 +200,7 @@ impl Index {
         Ok(Self {
             mount_point: if mount_point.is_empty() { None } else { Some(mount_point) },
             records,
+            secondary_index,
         })
     }
 
@@ -157,12 +217,83 @@ impl Index {
         &self.records
     }
 
+    /// Location and hashes of the path hash index and the full directory
+    /// index (version 10 and up).
+    #[inline]
+    pub fn secondary_index(&self) -> Option<&SecondaryIndexInfo> {
+        self.secondary_index.as_ref()
+    }
+
     #[inline]
     pub fn into_records<'a>(self) -> Vec<Record> {
         self.records
     }
 }
 
+/// Hash of a path (relative to the mount point) as used as key in the path hash index.
+///
+/// This is FNV-64 over the lower case UTF-16 path. Before version 11 Unreal Engine
+/// passed the character count instead of the byte count, so only the first half of
+/// the UTF-16 bytes are hashed.
+pub fn path_hash(path: &str, seed: u64, version: u32) -> u64 {
+    const FNV64_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV64_PRIME:  u64 = 0x00000100000001b3;
+
+    let bytes: Vec<u8> = path
+        .chars()
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+
+    let len = if version < PAK_FNV64_BUGFIX_VERSION {
+        bytes.len() / 2
+    } else {
+        bytes.len()
+    };
+
+    let mut hash = FNV64_OFFSET.wrapping_add(seed);
+    for byte in &bytes[..len] {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV64_PRIME);
+    }
+    hash
+}
+
+/// Reads the (decrypted) path hash index as pairs of path hash and location
+/// of the encoded record. The pruned directory index that follows is ignored.
+pub fn read_path_hash_index(mut data: &[u8]) -> Result<Vec<(u64, u32)>> {
+    let reader = &mut data;
+    decode!(reader, file_count: u32);
+    // don't trust the count for the allocation, each entry is 12 bytes
+    let mut entries = Vec::with_capacity((file_count as usize).min(reader.len() / 12));
+    for _ in 0..file_count {
+        decode!(reader, hash: u64, location: u32);
+        entries.push((hash, location));
+    }
+    Ok(entries)
+}
+
+/// Reads the (decrypted) full directory index as pairs of path (relative to
+/// the mount point) and location of the encoded record.
+pub fn read_full_directory_index(mut data: &[u8], encoding: Encoding) -> Result<Vec<(String, u32)>> {
+    let reader = &mut data;
+    decode!(reader, dir_count: u32);
+    let mut entries = Vec::new();
+    for _ in 0..dir_count {
+        let dirname = read_path(reader, encoding)?;
+        decode!(reader, file_count: u32);
+        for _ in 0..file_count {
+            let name = read_path(reader, encoding)?;
+            decode!(reader, location: u32);
+            let path = if dirname == "/" { name } else { format!("{}{}", dirname, name) };
+            entries.push((path, location));
+        }
+    }
+    Ok(entries)
+}
+
 pub fn read_path(reader: &mut impl Read, encoding: Encoding) -> Result<String> {
     let mut buf = [0; 4];
     reader.read_exact(&mut buf)?;
@@ -248,6 +379,7 @@ pub fn read_records(
     );
 
     let mut secondary_index_info = SecondaryIndexInfo::default();
+    secondary_index_info.path_hash_seed = path_hash_seed;
     secondary_index_info.has_path_hash_index = has_path_hash_index != 0;
 
     if secondary_index_info.has_path_hash_index {
@@ -261,6 +393,7 @@ pub fn read_records(
         secondary_index_info.has_path_hash_index = path_hash_index_size != -1;
         secondary_index_info.path_hash_index_offset = path_hash_index_offset;
         secondary_index_info.path_hash_index_size = path_hash_index_size;
+        secondary_index_info.path_hash_index_hash = path_hash_index_hash;
     }
     decode!(reader, has_full_directory_index: u32);
     secondary_index_info.has_full_directory_index = has_full_directory_index != 0;
@@ -275,6 +408,7 @@ pub fn read_records(
         secondary_index_info.has_full_directory_index = full_directory_index_size != -1;
         secondary_index_info.full_directory_index_offset = full_directory_index_offset;
         secondary_index_info.full_directory_index_size = full_directory_index_size;
+        secondary_index_info.full_directory_index_hash = full_directory_index_hash;
     }
     decode!(reader, pak_entries_size: i32);
     let mut pak_entries = vec![0u8; pak_entries_size as usize];
@@ -296,7 +430,10 @@ fn read_secondary_index_records<R>(
     reader: &mut R,
     index_info: &SecondaryIndexInfo,
     encryption_key: Option<Vec<u8>>,
-    encoding: Encoding
+    encoding: Encoding,
+    version: u32,
+    mount_point: Option<&str>,
+    dictionary: &Dictionary,
 ) -> Result<Vec<Record>> where
     R: Read,
     R: Seek,
@@ -362,7 +499,9 @@ fn read_secondary_index_records<R>(
             }
         }
     } else if index_info.has_path_hash_index {
-        warn!("Hash index is used as no full directory index was found. Filenames and paths can not be restored using this index!");
+        if dictionary.is_empty() {
+            warn!("Hash index is used as no full directory index was found. Filenames and paths can not be restored using this index!");
+        }
         debug!("Reading path hash index from {} with size {}", index_info.path_hash_index_offset, index_info.path_hash_index_size);
         let mut path_hash_index_data =
             vec![0u8; index_info.path_hash_index_size as usize];
@@ -381,20 +520,34 @@ fn read_secondary_index_records<R>(
             decrypt(&mut path_hash_index_data, &key);
         }
 
+        let names = dictionary.hashes(index_info.path_hash_seed, version, mount_point);
+        let mut unnamed_count = 0usize;
+
         let mut index_buff = &path_hash_index_data[..];
         decode!(&mut index_buff, file_count: u32);
         debug!("Found {} files in hash index", file_count);
         for _ in 0..file_count {
             decode!(&mut index_buff, hash: u64, entry: u32);
 
+            let name = if let Some(&name) = names.get(&hash) {
+                name.to_string()
+            } else {
+                unnamed_count += 1;
+                format!("{:x}", hash)
+            };
+
             encoded_record_info.seek(SeekFrom::Start(entry as u64))?;
-            trace!("Decoding file {:x} from location {}", hash, entry);
-            if let Ok(record) = Record::decode_entry(&mut encoded_record_info, format!("{:x}", hash)) {
+            trace!("Decoding file {} from location {}", name, entry);
+            if let Ok(record) = Record::decode_entry(&mut encoded_record_info, name) {
                 records.push(record);
             } else {
                 warn!("Failed to read record for file {:x}. Skipping.", hash);
             }
         }
+
+        if !dictionary.is_empty() && unnamed_count > 0 {
+            warn!("{} of {} files in hash index are not in the dictionary, their paths can not be restored!", unnamed_count, file_count);
+        }
     } else {
         warn!("Neither full direcotry nor hash index found! Files are probably missing!");
     }
@@ -4,38 +4,52 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{pak::{Pak, COMPR_NONE, COMPR_ZLIB, COMPR_BIAS_MEMORY, COMPR_BIAS_SPEED}, util::print_table};
+use crate::{pak::{Footer, Pak, COMPR_NONE, COMPR_ZLIB, COMPR_GZIP, COMPR_BIAS_MEMORY, COMPR_BIAS_SPEED, PAK_COMPRESSION_METHOD_NAMES_VERSION, PAK_ENCRYPTION_GUID_VERSION, PAK_FROZEN_INDEX_VERSION}, util::print_table};
+use crate::keyring::Keyring;
 use crate::result::Result;
-use crate::util::{format_size, Align};
+use crate::util::{format_guid, format_size, Align};
+
+/// Prints the footer and, if the index could be read, a summary of the
+/// records. `pak` is the result of reading the whole pak.
+pub fn info(footer: &Footer, pak: &Result<Pak>, keyring: &Keyring, human_readable: bool) -> Result<()> {
+    let pak = match pak {
+        Ok(pak) => pak,
+        Err(error) => {
+            print_footer(footer, None, keyring, 0);
+            println!();
+            println!("Index can't be read: {}", error);
+            return Ok(());
+        }
+    };
 
-pub fn info(pak: &Pak, human_readable: bool) -> Result<()> {
     let fmt_size = if human_readable {
         |size: u64| format_size(size)
     } else {
         |size: u64| format!("{}", size)
     };
 
+    // compression methods that are listed even if no file uses them
+    let known_methods: Vec<u32> = if pak.version() >= PAK_COMPRESSION_METHOD_NAMES_VERSION {
+        (1..=pak.compression_methods().len() as u32).collect()
+    } else {
+        vec![COMPR_ZLIB, COMPR_GZIP, COMPR_BIAS_SPEED, COMPR_BIAS_MEMORY]
+    };
+
     let mut sum_size              = 0;
     let mut sum_uncompressed_size = 0;
     let mut uncompr_count     = 0usize;
-    let mut zlib_count        = 0usize;
-    let mut bias_speed_count  = 0usize;
-    let mut bias_memory_count = 0usize;
     let mut other_count       = 0usize;
     let mut encrypted_count   = 0usize;
     let mut sum_uncompr_size     = 0;
-    let mut sum_zlib_size        = 0;
-    let mut sum_bias_speed_size  = 0;
-    let mut sum_bias_memory_size = 0;
     let mut sum_unknown_size     = 0;
     let mut sum_encrypted_size   = 0;
 
-    let mut sum_uncompr_zlib_size        = 0;
-    let mut sum_uncompr_bias_speed_size  = 0;
-    let mut sum_uncompr_bias_memory_size = 0;
     let mut sum_uncompr_unknown_size     = 0;
     let mut sum_uncompr_encrypted_size   = 0;
 
+    // count, size, uncompressed size
+    let mut method_sums = vec![(0usize, 0, 0); known_methods.len()];
+
     for record in pak.index().records() {
         sum_size += record.size();
         sum_uncompressed_size += record.uncompressed_size();
@@ -44,51 +58,68 @@ pub fn info(pak: &Pak, human_readable: bool) -> Result<()> {
             sum_encrypted_size += record.size();
             sum_uncompr_encrypted_size += record.uncompressed_size();
         }
-        match record.compression_method() {
-            self::COMPR_NONE => {
-                uncompr_count += 1;
-                sum_uncompr_size += record.size();
-            }
-            self::COMPR_ZLIB => {
-                zlib_count += 1;
-                sum_zlib_size += record.size();
-                sum_uncompr_zlib_size += record.uncompressed_size();
-            }
-            self::COMPR_BIAS_SPEED => {
-                bias_speed_count += 1;
-                sum_bias_speed_size += record.size();
-                sum_uncompr_bias_speed_size += record.uncompressed_size();
-            }
-            self::COMPR_BIAS_MEMORY => {
-                bias_memory_count += 1;
-                sum_bias_memory_size += record.size();
-                sum_uncompr_bias_memory_size += record.uncompressed_size();
-            }
-            _ => {
-                other_count += 1;
-                sum_unknown_size += record.size();
-                sum_uncompr_unknown_size += record.uncompressed_size();
-            }
+        if record.compression_method() == COMPR_NONE {
+            uncompr_count += 1;
+            sum_uncompr_size += record.size();
+        } else if let Some(index) = known_methods.iter().position(|&method| method == record.compression_method()) {
+            let (count, size, uncompressed_size) = &mut method_sums[index];
+            *count += 1;
+            *size += record.size();
+            *uncompressed_size += record.uncompressed_size();
+        } else {
+            other_count += 1;
+            sum_unknown_size += record.size();
+            sum_uncompr_unknown_size += record.uncompressed_size();
         }
     }
 
-    println!("Pak Version: {}", pak.version());
-    println!("Mount Point: {}", pak.index().mount_point().unwrap_or(""));
-    println!();
+    print_footer(footer, Some(pak), keyring, encrypted_count);
+
+    let mut body = vec![
+        vec!["Files:".to_string(),    format!("{}", pak.index().records().len()), fmt_size(sum_size),         fmt_size(sum_uncompressed_size)],
+        vec!["Uncompr.:".to_string(), format!("{}", uncompr_count),               fmt_size(sum_uncompr_size), String::new()],
+    ];
+
+    for (&method, &(count, size, uncompressed_size)) in known_methods.iter().zip(method_sums.iter()) {
+        body.push(vec![
+            format!("{} Compr.:", pak.compression_method_name(method)),
+            format!("{}", count),
+            fmt_size(size),
+            fmt_size(uncompressed_size),
+        ]);
+    }
+
+    body.push(vec!["Unknown Compr.:".to_string(), format!("{}", other_count),     fmt_size(sum_unknown_size),   fmt_size(sum_uncompr_unknown_size)]);
+    body.push(vec!["Encrypted:".to_string(),      format!("{}", encrypted_count), fmt_size(sum_encrypted_size), fmt_size(sum_uncompr_encrypted_size)]);
 
     print_table(
         &["", "Count", "Size", "Uncompr."],
         &[Align::Left, Align::Right, Align::Right, Align::Right],
-        &[
-            vec!["Files:",              &format!("{}", pak.index().records().len()), &fmt_size(sum_size),             &fmt_size(sum_uncompressed_size)],
-            vec!["Uncompr.:",           &format!("{}", uncompr_count),       &fmt_size(sum_uncompr_size),     ""],
-            vec!["ZLIB Compr.:",        &format!("{}", zlib_count),          &fmt_size(sum_zlib_size),        &fmt_size(sum_uncompr_zlib_size)],
-            vec!["Bias Speed Compr.:",  &format!("{}", bias_speed_count),    &fmt_size(sum_bias_speed_size),  &fmt_size(sum_uncompr_bias_speed_size)],
-            vec!["Bias Memory Compr.:", &format!("{}", bias_memory_count),   &fmt_size(sum_bias_memory_size), &fmt_size(sum_uncompr_bias_memory_size)],
-            vec!["Unknown Compr.:",     &format!("{}", other_count),         &fmt_size(sum_unknown_size),     &fmt_size(sum_uncompr_unknown_size)],
-            vec!["Encrypted:",          &format!("{}", encrypted_count),     &fmt_size(sum_encrypted_size),   &fmt_size(sum_uncompr_encrypted_size)],
-        ],
+        &body,
     );
 
     Ok(())
 }
+
+fn print_footer(footer: &Footer, pak: Option<&Pak>, keyring: &Keyring, encrypted_count: usize) {
+    println!("Pak Version: {}", footer.version());
+    if let Some(pak) = pak {
+        println!("Mount Point: {}", pak.index().mount_point().unwrap_or(""));
+    }
+    if footer.version() == PAK_FROZEN_INDEX_VERSION {
+        println!("Frozen Index: {}", if footer.frozen_index() { "yes" } else { "no" });
+    }
+    if footer.encrypted_index() || encrypted_count > 0 {
+        println!("Encrypted Index: {}", if footer.encrypted_index() { "yes" } else { "no" });
+        if footer.version() >= PAK_ENCRYPTION_GUID_VERSION {
+            println!("Encryption Key GUID: {}", format_guid(footer.encryption_guid()));
+        }
+        println!("Keyring Key: {}", if keyring.get(footer.encryption_guid()).is_some() { "found" } else { "not found" });
+        if let Some(pak) = pak {
+            println!("Encryption Key: {}", if pak.encryption_key().is_some() { "available" } else { "missing" });
+        }
+    }
+    if pak.is_none() && footer.version() >= PAK_COMPRESSION_METHOD_NAMES_VERSION {
+        println!("Compression Methods: {}", footer.compression_methods().join(", "));
+    }
+}
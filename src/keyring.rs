@@ -0,0 +1,134 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Encryption keys selected by the encryption key GUID of a pak footer.
+//!
+//! A keyring file maps GUIDs to base64 encoded 32 byte AES-256 keys. It is
+//! either a JSON object:
+//!
+//! ```json
+//! {
+//!     "00000000000000000000000000000000": "<base64 key>",
+//!     "1A2B3C4D-00000000-00000000-00000001": "<base64 key>"
+//! }
+//! ```
+//!
+//! or a TOML table with the same structure:
+//!
+//! ```toml
+//! "00000000000000000000000000000000" = "<base64 key>"
+//! "1A2B3C4D-00000000-00000000-00000001" = "<base64 key>"
+//! ```
+//!
+//! Paks before version 7 have no GUID in the footer and use the all zero GUID.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{Error, Result, util::{format_guid, parse_guid}};
+
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: HashMap<u128, Vec<u8>>,
+}
+
+impl Keyring {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a keyring file. Files ending in `.toml` are parsed as TOML,
+    /// everything else as JSON.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(error) => return Err(Error::io_with_path(error, path)),
+        };
+
+        let is_toml = path.extension()
+            .map(|ext| ext.eq_ignore_ascii_case("toml"))
+            .unwrap_or(false);
+
+        let result = if is_toml {
+            Self::from_toml(&data)
+        } else {
+            Self::from_json(&data)
+        };
+
+        result.map_err(|error| error.with_path(path))
+    }
+
+    pub fn from_json(data: &str) -> Result<Self> {
+        match serde_json::from_str::<HashMap<String, String>>(data) {
+            Ok(entries) => Self::from_entries(entries),
+            Err(error) => Err(Error::new(format!("illegal keyring JSON: {}", error))),
+        }
+    }
+
+    pub fn from_toml(data: &str) -> Result<Self> {
+        match toml::from_str::<HashMap<String, String>>(data) {
+            Ok(entries) => Self::from_entries(entries),
+            Err(error) => Err(Error::new(format!("illegal keyring TOML: {}", error))),
+        }
+    }
+
+    fn from_entries(entries: HashMap<String, String>) -> Result<Self> {
+        let mut keyring = Self::new();
+        for (guid, key) in entries {
+            let guid = parse_guid(&guid)?;
+            let key = match base64::decode(key.trim()) {
+                Ok(key) => key,
+                Err(error) => return Err(Error::new(format!(
+                    "illegal encryption key for GUID {}: {}", format_guid(guid), error))),
+            };
+            keyring.insert(guid, key)?;
+        }
+        Ok(keyring)
+    }
+
+    /// Adds a key, replacing any previous key of the same GUID.
+    pub fn insert(&mut self, guid: u128, key: Vec<u8>) -> Result<()> {
+        if key.len() != 32 {
+            return Err(Error::new(format!(
+                "encryption key for GUID {} needs to be 32 bytes long, but is {} bytes long",
+                format_guid(guid), key.len())));
+        }
+        self.keys.insert(guid, key);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get(&self, guid: u128) -> Option<&[u8]> {
+        self.keys.get(&guid).map(Vec::as_slice)
+    }
+
+    #[inline]
+    pub fn contains(&self, guid: u128) -> bool {
+        self.keys.contains_key(&guid)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn guids(&self) -> impl Iterator<Item=u128> + '_ {
+        self.keys.keys().copied()
+    }
+}
+
+// don't print the keys themselves
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.keys.keys().map(|&guid| format_guid(guid))).finish()
+    }
+}
@@ -1,7 +1,12 @@
 pub mod pak;
 pub use pak::{Pak, Variant};
 
+pub mod codec;
+pub mod plugin;
 pub mod decrypt;
+pub mod encrypt;
+pub mod keyring;
+pub mod dictionary;
 pub mod index;
 pub mod result;
 pub use result::{Error, Result};
@@ -9,7 +14,11 @@ pub use result::{Error, Result};
 pub mod record;
 pub use record::Record;
 
+pub mod entry;
+pub use entry::EntryReader;
+
 pub mod info;
+pub mod conflicts;
 pub mod util;
 pub mod decode;
 pub mod encode;
@@ -18,9 +27,14 @@ pub use filter::Filter;
 
 pub mod unpack;
 pub mod pack;
+pub use pack::PakWriter;
 pub mod check;
 
-pub mod reopen;
+pub mod pakset;
+pub use pakset::PakSet;
+pub mod read_at;
+pub use read_at::{ReadAt, ReadAtCursor};
+pub mod http;
 pub mod walkdir;
 
 #[cfg(target_os = "linux")]
@@ -4,28 +4,25 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{collections::HashMap, ffi::OsStr, fs::File, io::Read, path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
-use std::os::unix::fs::FileExt;
+use std::{borrow::Cow, collections::HashMap, ffi::OsStr, io::{Read, Seek, SeekFrom}, os::raw::c_int, fs::File, path::Path, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 use std::os::linux::fs::MetadataExt;
 
 use cntr_fuse as fuse;
-use flate2::bufread::ZlibDecoder;
-use fuse::{Filesystem, FileType, Request, ReplyEntry, FileAttr, ReplyAttr, ReplyEmpty, ReplyOpen, ReplyDirectory, ReplyStatfs, ReplyRead, FUSE_ROOT_ID};
+use fuse::{Filesystem, FileType, Request, ReplyEntry, FileAttr, ReplyAttr, ReplyEmpty, ReplyOpen, ReplyDirectory, ReplyStatfs, ReplyRead, ReplyXattr, FUSE_ROOT_ID};
 use daemonize::{Daemonize, DaemonizeError};
-use libc::{ENOENT, EISDIR, EACCES, ENOTDIR, EINVAL, EIO, ENOSYS, O_RDONLY};
+use libc::{ENOENT, EISDIR, EACCES, ENOTDIR, EINVAL, EIO, ENOSYS, ENODATA, ERANGE, O_RDONLY};
 
-use crate::{Error, Pak, Record, Result, pak::{self, Variant}, record::CompressionBlock, util::{make_pak_path, parse_pak_path}};
+use crate::{EntryReader, Error, Pak, PakSet, ReadAt, ReadAtCursor, Record, Result, pak::COMPR_NONE, codec::Codec, entry::EntryLayout, util::{make_pak_path, parse_pak_path}};
 
 #[derive(Debug)]
 enum INodeData {
     File {
-        offset: u64,
-        size: u64,
-        uncompressed_size: u64,
+        // index into U4PakFS::sources
+        source: usize,
         compression_method: u32,
-        compression_blocks: Option<Vec<CompressionBlock>>,
-        encrypted: bool,
-        compression_block_size: u32,
+        // None if not compressed or there is no codec for the compression method
+        codec: Option<Arc<dyn Codec>>,
+        layout: EntryLayout,
     },
     Dir(HashMap<String, u64>)
 }
@@ -51,9 +48,23 @@ impl INode {
     }
 }
 
+/// Extended attribute with the path of the pak a file comes from. Only set
+/// for file systems of several paks.
+pub const PAK_XATTR: &str = "user.u4pak.pak";
+
+// reply of listxattr, names are null terminated
+const PAK_XATTR_LIST: &[u8] = b"user.u4pak.pak\0";
+
 #[derive(Debug)]
-pub struct U4PakFS {
-    file: File,
+struct Source<R> {
+    reader: R,
+    encryption_key: Option<Vec<u8>>,
+    path: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct U4PakFS<R> {
+    sources: Vec<Source<R>>,
     inodes: Vec<INode>,
 
     atime:  SystemTime,
@@ -68,12 +79,13 @@ pub struct U4PakFS {
     blocks:  u64,
 }
 
-impl U4PakFS {
-    pub fn new(pak: &Pak, file: File) -> Result<Self> {
+impl U4PakFS<File> {
+    /// Times and owner of the files are the ones of the pak file.
+    pub fn from_file(pak: &Pak, file: File, encryption_key: Option<Vec<u8>>) -> Result<Self> {
         let meta = file.metadata()?;
 
-        let mut u4pakfs = U4PakFS {
-            file,
+        U4PakFS {
+            sources: vec![Source { reader: file, encryption_key, path: None }],
             inodes: Vec::new(),
 
             atime:  make_time(meta.st_atime(), meta.st_atime_nsec()),
@@ -86,37 +98,112 @@ impl U4PakFS {
 
             blksize: meta.st_blksize(),
             blocks:  0,
+        }.insert_all(pak)
+    }
+
+    /// Overlay of all paks of the set, with the winning record of each
+    /// path. Times and owner of the files are the ones of the pak file with
+    /// the highest priority.
+    pub fn from_set(set: &PakSet) -> Result<Self> {
+        let mut sources = Vec::with_capacity(set.layers().len());
+        for layer in set.layers() {
+            let file = match File::open(layer.path()) {
+                Ok(file) => file,
+                Err(error) => return Err(Error::io_with_path(error, layer.path())),
+            };
+            sources.push(Source {
+                reader: file,
+                encryption_key: layer.pak().encryption_key().map(<[u8]>::to_vec),
+                path: Some(layer.path().to_string_lossy().into_owned()),
+            });
+        }
+
+        let meta = match sources.last() {
+            Some(source) => source.reader.metadata()?,
+            None => return Err(Error::new("no paks to mount".to_string())),
         };
 
-        u4pakfs.inodes.push(INode {
+        let mut fs = U4PakFS {
+            sources,
+            inodes: Vec::new(),
+
+            atime:  make_time(meta.st_atime(), meta.st_atime_nsec()),
+            mtime:  make_time(meta.st_mtime(), meta.st_mtime_nsec()),
+            ctime:  make_time(meta.st_ctime(), meta.st_ctime_nsec()),
+            crtime: meta.created().unwrap_or(UNIX_EPOCH),
+
+            uid:    meta.st_uid(),
+            gid:    meta.st_gid(),
+
+            blksize: meta.st_blksize(),
+            blocks:  0,
+        };
+
+        fs.insert_root();
+        for entry in set.entries() {
+            fs.insert(entry.layer_index(), entry.layer().pak(), entry.path(), entry.record())?;
+        }
+
+        Ok(fs)
+    }
+}
+
+impl<R: ReadAt> U4PakFS<R> {
+    /// Files are owned by the current user and have the current time.
+    pub fn new(pak: &Pak, reader: R, encryption_key: Option<Vec<u8>>) -> Result<Self> {
+        let now = SystemTime::now();
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+
+        U4PakFS {
+            sources: vec![Source { reader, encryption_key, path: None }],
+            inodes: Vec::new(),
+
+            atime:  now,
+            mtime:  now,
+            ctime:  now,
+            crtime: now,
+
+            uid,
+            gid,
+
+            blksize: 4096,
+            blocks:  0,
+        }.insert_all(pak)
+    }
+
+    // adds the root directory and the records of the pak
+    fn insert_all(mut self, pak: &Pak) -> Result<Self> {
+        self.insert_root();
+
+        for record in pak.index().records() {
+            self.insert(0, pak, record.filename(), record)?;
+        }
+
+        Ok(self)
+    }
+
+    fn insert_root(&mut self) {
+        self.inodes.push(INode {
             parent: FUSE_ROOT_ID,
             inode:  FUSE_ROOT_ID,
             data: INodeData::Dir(HashMap::new()),
             stat: FileAttr {
                 ino:    FUSE_ROOT_ID,
                 size:   5,
-                blocks: 1 + ((5 - 1) / u4pakfs.blksize),
-                atime:  u4pakfs.atime,
-                mtime:  u4pakfs.mtime,
-                ctime:  u4pakfs.ctime,
-                crtime: u4pakfs.crtime,
+                blocks: 1 + ((5 - 1) / self.blksize),
+                atime:  self.atime,
+                mtime:  self.mtime,
+                ctime:  self.ctime,
+                crtime: self.crtime,
                 kind:   FileType::Directory,
                 perm:   0o555,
                 nlink:  1,
-                uid:    u4pakfs.uid,
-                gid:    u4pakfs.gid,
+                uid:    self.uid,
+                gid:    self.gid,
                 rdev:   0,
                 flags:  0,
             },
         });
-
-        let version = pak.version();
-        let variant = pak.variant();
-        for record in pak.index().records() {
-            u4pakfs.insert(variant, version, record)?;
-        }
-
-        Ok(u4pakfs)
     }
 
     #[inline]
@@ -124,9 +211,65 @@ impl U4PakFS {
         self.inodes.get((inode - FUSE_ROOT_ID) as usize)
     }
 
-    fn insert(&mut self, variant: Variant, version: u32, record: &Record) -> Result<u64> {
+    /// Inode of a path relative to the root of the file system.
+    pub fn lookup_path(&self, path: &str) -> Option<u64> {
+        let mut inode = FUSE_ROOT_ID;
+        for name in parse_pak_path(path) {
+            match &self.get(inode)?.data {
+                INodeData::Dir(children) => inode = *children.get(name)?,
+                INodeData::File { .. } => return None,
+            }
+        }
+        Some(inode)
+    }
+
+    /// Path of the pak a file comes from, i.e. the value of [`PAK_XATTR`].
+    pub fn pak_path(&self, inode: u64) -> Option<&str> {
+        match &self.get(inode)?.data {
+            INodeData::File { source, .. } => self.sources[*source].path.as_deref(),
+            INodeData::Dir(_) => None,
+        }
+    }
+
+    /// Reads up to `read_size` bytes of a file at `read_offset`, like the
+    /// FUSE read operation. Errors are errno values.
+    pub fn read_file(&self, ino: u64, read_offset: u64, read_size: u32) -> std::result::Result<Vec<u8>, c_int> {
+        if let Some(inode_data) = self.get(ino) {
+            if let INodeData::File { source, compression_method, codec, layout } = &inode_data.data {
+                let source = &self.sources[*source];
+                if layout.encrypted && source.encryption_key.is_none() {
+                    return Err(EACCES);
+                }
+
+                let codec = match (*compression_method, codec) {
+                    (COMPR_NONE, _)   => None,
+                    (_, Some(codec)) => Some(codec.as_ref()),
+                    (_, None)        => return Err(ENOSYS),
+                };
+
+                let mut reader = EntryReader::from_layout(
+                    ReadAtCursor::new(&source.reader), Cow::Borrowed(layout),
+                    codec, source.encryption_key.as_deref());
+
+                let size = layout.uncompressed_size.saturating_sub(read_offset).min(read_size as u64);
+                let mut buffer = Vec::with_capacity(size as usize);
+                if let Err(error) = reader.seek(SeekFrom::Start(read_offset))
+                        .and_then(|_| reader.take(size).read_to_end(&mut buffer)) {
+                    return Err(error.raw_os_error().unwrap_or(EIO));
+                }
+
+                Ok(buffer)
+            } else {
+                Err(EISDIR)
+            }
+        } else {
+            Err(ENOENT)
+        }
+    }
+
+    fn insert(&mut self, source: usize, pak: &Pak, filename: &str, record: &Record) -> Result<u64> {
         let mut parent = FUSE_ROOT_ID;
-        let path: Vec<_> = parse_pak_path(record.filename()).collect();
+        let path: Vec<_> = parse_pak_path(filename).collect();
 
         if path.len() > 1 {
             for (index, &name) in path[0..path.len() - 1].iter().enumerate() {
@@ -178,7 +321,7 @@ impl U4PakFS {
 
             if let INodeData::Dir(children) = &mut parent_inode.data {
                 if children.contains_key(name) {
-                    return Err(Error::new(format!("{}: file already exists", record.filename())));
+                    return Err(Error::new(format!("{}: file already exists", filename)));
                 }
 
                 parent_inode.stat.nlink += 1;
@@ -203,32 +346,16 @@ impl U4PakFS {
                     crtime = self.crtime;
                 }
 
-                let offset = record.offset();
-                let compression_blocks;
-                if version < 7 {
-                    compression_blocks = (*record.compression_blocks()).clone();
-                } else if let Some(blocks) = record.compression_blocks() {
-                    compression_blocks = Some(blocks.iter().map(|block| CompressionBlock {
-                        start_offset: offset + block.start_offset,
-                        end_offset:   offset + block.end_offset,
-                    }).collect());
-                } else {
-                    compression_blocks = None;
-                }
-
                 let uncompressed_size = record.uncompressed_size();
 
                 self.inodes.push(INode {
                     parent,
                     inode: new_inode,
                     data: INodeData::File {
-                        offset: offset + pak::Pak::header_size(version, variant, record),
-                        size: record.size(),
-                        uncompressed_size,
+                        source,
                         compression_method: record.compression_method(),
-                        compression_blocks,
-                        encrypted: record.encrypted(),
-                        compression_block_size: record.compression_block_size(),
+                        codec: pak.codec(record.compression_method()).cloned(),
+                        layout: EntryLayout::new(pak, record),
                     },
                     stat: FileAttr {
                         ino:    new_inode,
@@ -261,7 +388,7 @@ impl U4PakFS {
 
 const TTL: Duration = Duration::from_secs(std::u64::MAX);
 
-impl Filesystem for U4PakFS {
+impl<R: ReadAt> Filesystem for U4PakFS<R> {
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         if let Some(mut inode_data) = self.get(parent) {
             if "." == name {
@@ -391,114 +518,45 @@ impl Filesystem for U4PakFS {
     }
 
     fn read(&mut self, _req: &Request, ino: u64, _fh: u64, read_offset: i64, read_size: u32, reply: ReplyRead) {
-        if let Some(inode_data) = self.get(ino) {
-            if let INodeData::File {
-                    compression_method,
-                    compression_block_size,
-                    compression_blocks,
-                    encrypted,
-                    offset,
-                    size,
-                    uncompressed_size,
-            } = &inode_data.data {
-                if *encrypted {
-                    return reply.error(ENOSYS);
-                }
+        if read_offset < 0 {
+            return reply.error(EINVAL);
+        }
 
-                if read_offset < 0 {
-                    return reply.error(EINVAL);
-                }
+        match self.read_file(ino, read_offset as u64, read_size) {
+            Ok(data) => reply.data(&data),
+            Err(errno) => reply.error(errno),
+        }
+    }
 
-                let uncompressed_size = *uncompressed_size;
-                if read_offset as u64 >= uncompressed_size {
-                    return reply.data(&[]);
-                }
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        if self.get(ino).is_none() {
+            return reply.error(ENOENT);
+        }
 
-                let offset = *offset;
-                match *compression_method {
-                    pak::COMPR_NONE => {
-                        let read_offset = offset + read_offset as u64;
-                        let end_offset = std::cmp::min(offset + uncompressed_size, read_offset + read_size as u64);
-                        let read_size = end_offset - read_offset;
+        let path = if name == PAK_XATTR { self.pak_path(ino) } else { None };
 
-                        let mut buffer = vec![0; read_size as usize];
-                        if let Err(error) = self.file.read_exact_at(&mut buffer, read_offset) {
-                            return reply.error(error.raw_os_error().unwrap_or(EIO));
-                        }
+        match path {
+            None => reply.error(ENODATA),
+            Some(path) if size == 0 => reply.size(path.len() as u32),
+            Some(path) if (size as usize) < path.len() => reply.error(ERANGE),
+            Some(path) => reply.data(path.as_bytes()),
+        }
+    }
 
-                        return reply.data(&buffer);
-                    }
-                    pak::COMPR_ZLIB => {
-                        if let Some(blocks) = compression_blocks {
-                            let compression_block_size = *compression_block_size as u64;
-                            let end_offset = std::cmp::min(read_offset as u64 + read_size as u64, uncompressed_size);
-                            let start_block_index   = (read_offset as u64 / compression_block_size) as usize;
-                            let mut end_block_index = (end_offset         / compression_block_size) as usize;
-
-                            if end_offset % compression_block_size != 0 {
-                                end_block_index += 1;
-                            }
-
-                            let mut current_offset = compression_block_size * start_block_index as u64;
-                            let mut in_buffer = Vec::new();
-                            let mut out_buffer = Vec::new();
-                            for block in &blocks[start_block_index..end_block_index] {
-                                let block_size = block.end_offset - block.start_offset;
-                                in_buffer.resize(block_size as usize, 0);
-                                if let Err(error) = self.file.read_exact_at(&mut in_buffer, block.start_offset) {
-                                    return reply.error(error.raw_os_error().unwrap_or(EIO));
-                                }
-
-                                let mut zlib = ZlibDecoder::new(&in_buffer[..]);
-
-                                if current_offset < read_offset as u64 {
-                                    out_buffer.resize(std::cmp::min(compression_block_size, end_offset) as usize, 0);
-                                    if let Err(error) = zlib.read_exact(&mut out_buffer) {
-                                        return reply.error(error.raw_os_error().unwrap_or(EIO));
-                                    }
-                                    out_buffer.drain(0..read_offset as usize);
-                                } else if end_offset < current_offset + compression_block_size {
-                                    let remaining = end_offset - current_offset;
-                                    let index = out_buffer.len();
-                                    out_buffer.resize(index + remaining as usize, 0);
-                                    if let Err(error) = zlib.read_exact(&mut out_buffer[index..]) {
-                                        return reply.error(error.raw_os_error().unwrap_or(EIO));
-                                    }
-                                } else if let Err(error) = zlib.read_to_end(&mut out_buffer) {
-                                    return reply.error(error.raw_os_error().unwrap_or(EIO));
-                                }
-                                current_offset += compression_block_size;
-                            }
-
-                            return reply.data(&out_buffer);
-                        } else {
-                            // version 2 has compression support, but not compression blocks
-                            let size = *size;
-                            let mut in_buffer = vec![0u8; size as usize];
-                            let mut out_buffer = Vec::with_capacity(uncompressed_size as usize);
-                            if let Err(error) = self.file.read_exact_at(&mut in_buffer, offset) {
-                                return reply.error(error.raw_os_error().unwrap_or(EIO));
-                            }
-
-                            let mut zlib = ZlibDecoder::new(&in_buffer[..]);
-                            if let Err(error) = zlib.read_to_end(&mut out_buffer) {
-                                return reply.error(error.raw_os_error().unwrap_or(EIO));
-                            }
-
-                            if (read_size as usize) < out_buffer.len() {
-                                return reply.data(&out_buffer[0..read_size as usize]);
-                            }
-                            return reply.data(&out_buffer);
-                        }
-                    }
-                    _ => return reply.error(ENOSYS)
-                }
-            } else {
-                return reply.error(EISDIR);
-            }
-        } else {
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        if self.get(ino).is_none() {
             return reply.error(ENOENT);
         }
+
+        let names: &[u8] = if self.pak_path(ino).is_some() { PAK_XATTR_LIST } else { b"" };
+
+        if size == 0 {
+            reply.size(names.len() as u32);
+        } else if (size as usize) < names.len() {
+            reply.error(ERANGE);
+        } else {
+            reply.data(names);
+        }
     }
 }
 
@@ -524,6 +582,25 @@ impl From<DaemonizeError> for Error {
 }
 
 pub fn mount(pak: Pak, file: File, mountpt: impl AsRef<Path>, options: MountOptions) -> Result<()> {
+    let fs = U4PakFS::from_file(&pak, file, pak.encryption_key().map(<[u8]>::to_vec))?;
+
+    drop(pak);
+
+    mount_fs(fs, mountpt, options)
+}
+
+/// Mounts the merged view of several paks. Files have the extended attribute
+/// [`PAK_XATTR`] with the path of the pak they come from.
+pub fn mount_set(set: PakSet, mountpt: impl AsRef<Path>, options: MountOptions) -> Result<()> {
+    let fs = U4PakFS::from_set(&set)?;
+
+    drop(set);
+
+    mount_fs(fs, mountpt, options)
+}
+
+/// Mounts a file system of a pak that was read from any [`ReadAt`].
+pub fn mount_fs<R: ReadAt>(fs: U4PakFS<R>, mountpt: impl AsRef<Path>, options: MountOptions) -> Result<()> {
     let mountpt = match mountpt.as_ref().canonicalize() {
         Ok(mountpt) => mountpt,
         Err(error) => return Err(Error::io_with_path(error, mountpt))
@@ -543,10 +620,6 @@ pub fn mount(pak: Pak, file: File, mountpt: impl AsRef<Path>, options: MountOpti
         foreground = options.foreground;
     }
 
-    let fs = U4PakFS::new(&pak, file)?;
-
-    drop(pak);
-
     if !foreground {
         let daemonize = Daemonize::new()
             .working_directory("/")
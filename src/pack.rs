@@ -4,24 +4,26 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{collections::HashMap, convert::TryFrom, io::{BufWriter, Read, Seek, SeekFrom, Write}, num::{NonZeroU32, NonZeroUsize, NonZeroU64}, path::{Path, PathBuf}, time::UNIX_EPOCH};
+use std::{collections::{BTreeMap, HashMap, HashSet}, convert::TryFrom, io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write}, num::{NonZeroU32, NonZeroUsize, NonZeroU64}, path::{Path, PathBuf}, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
 use std::fs::{OpenOptions, File};
 
 use crossbeam_channel::{Receiver, Sender, unbounded};
 use crossbeam_utils::thread;
+use aes::BLOCK_SIZE;
 use openssl::sha::Sha1 as OpenSSLSha1;
-use flate2::{Compression, write::ZlibEncoder};
 
 use crate::{Result, pak::{BUFFER_SIZE, COMPRESSION_BLOCK_HEADER_SIZE, CONAN_EXILE_RECORD_HEADER_SIZE, DEFAULT_COMPRESSION_LEVEL, V1_RECORD_HEADER_SIZE, V2_RECORD_HEADER_SIZE, V3_RECORD_HEADER_SIZE, Variant}, record::CompressionBlock, walkdir::walkdir};
 use crate::Pak;
+use crate::codec::CodecRegistry;
 use crate::result::Error;
-use crate::pak::{PAK_MAGIC, Sha1, COMPR_NONE, COMPR_ZLIB, DEFAULT_BLOCK_SIZE, DEFAULT_MIN_COMPRESSION_SIZE, compression_method_name};
+use crate::pak::{Footer, PAK_MAGIC, PAK_COMPRESSION_METHOD_COUNT, PAK_COMPRESSION_METHOD_NAMES_VERSION, PAK_COMPRESSION_METHOD_SIZE, PAK_FROZEN_INDEX_VERSION, PAK_PATH_HASH_INDEX_VERSION, Sha1, COMPR_NONE, COMPR_ZLIB, COMPR_GZIP, COMPR_LZ4, COMPR_CUSTOM, DEFAULT_BLOCK_SIZE, DEFAULT_MIN_COMPRESSION_SIZE, compression_method_name};
 use crate::record::Record;
-use crate::util::{make_pak_path, parse_compression_level, parse_pak_path, parse_size};
+use crate::util::{align, make_pak_path, parse_compression_level, parse_pak_path, parse_size, sha1_digest, str_crc32};
+use crate::encrypt::{encrypt, pad_and_encrypt};
 use crate::encode;
 use crate::encode::Encode;
 use crate::index::Encoding;
-use crate::index::Index;
+use crate::index::{Index, path_hash};
 
 pub const COMPR_DEFAULT: u32 = u32::MAX;
 
@@ -64,6 +66,10 @@ impl TryFrom<&str> for PackPath {
                 for param in param_str.split(',') {
                     if param.eq_ignore_ascii_case("zlib") {
                         compression_method = COMPR_ZLIB;
+                    } else if param.eq_ignore_ascii_case("gzip") {
+                        compression_method = COMPR_GZIP;
+                    } else if param.eq_ignore_ascii_case("lz4") {
+                        compression_method = COMPR_LZ4;
                     } else if param.eq_ignore_ascii_case("none") {
                         compression_method = COMPR_NONE;
                     } else if let Some(index) = param.find('=') {
@@ -119,7 +125,7 @@ impl TryFrom<&str> for PackPath {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PackOptions<'a> {
     pub variant: Variant,
     pub version: u32,
@@ -132,6 +138,13 @@ pub struct PackOptions<'a> {
     pub verbose: bool,
     pub null_separated: bool,
     pub thread_count: NonZeroUsize,
+    pub codecs: Arc<CodecRegistry>,
+    /// AES-256 key to encrypt file data and index with.
+    pub encryption_key: Option<Vec<u8>>,
+    /// Only encrypt the index, not the file data.
+    pub encrypt_index_only: bool,
+    /// GUID of the encryption key written into the footer (version 7 and up).
+    pub encryption_key_guid: u128,
 }
 
 impl Default for PackOptions<'_> {
@@ -148,52 +161,21 @@ impl Default for PackOptions<'_> {
             verbose: false,
             null_separated: false,
             thread_count: NonZeroUsize::new(num_cpus::get()).unwrap_or(NonZeroUsize::new(1).unwrap()),
+            codecs: Arc::new(CodecRegistry::default()),
+            encryption_key: None,
+            encrypt_index_only: false,
+            encryption_key_guid: 0,
         }
     }
 }
 
 pub fn pack(pak_path: impl AsRef<Path>, paths: &[PackPath], options: PackOptions) -> Result<Pak> {
-    let write_record_inline = match options.variant {
-        Variant::ConanExiles => {
-            return Err(Error::new("Writing of Conan Exile paks is not supported.".to_string()).
-                with_path(pak_path));
-            // XXX: There a are 20 unknown bytes after the inline record information if compressed.
-            //      That is 16 extra to the already 4 extra bytes in standard version >= 4.
-            //      In the index record there are only 4 extra bytes that are always 0.
-            //if options.version != 4 {
-            //    return Err(Error::new(format!(
-            //        "Only know how to handle Conan Exile paks of version 4, but version was {}.",
-            //        options.version)).
-            //        with_path(pak_path));
-            //}
-            //Record::write_conan_exiles_inline
-        }
-        Variant::Standard => match options.version {
-            1 => Record::write_v1_inline,
-            2 => Record::write_v2_inline,
-            3 => Record::write_v3_inline,
-            // XXX: There is an unknown 32bit field after the inline(!) record information if compressed.
-            // 4 => Record::write_v3_inline, // maybe?
-            // 5 => Record::write_v3_inline, // maybe?
-            // 7 => Record::write_v3_inline, // maybe?
-            _ => {
-                return Err(Error::new(
-                    format!("unsupported version: {}", options.version)).
-                    with_path(pak_path));
-            }
-        }
-    };
+    let pak_path = pak_path.as_ref();
 
-    match options.compression_method {
-        self::COMPR_NONE | self::COMPR_ZLIB => {}
-        _ => return Err(Error::new(
-            format!("unsupported compression method: {} ({})",
-                compression_method_name(options.compression_method), options.compression_method)).
-            with_path(pak_path))
-    }
+    // check before the file is created/truncated
+    check_options(&options).map_err(|error| error.with_path(pak_path))?;
 
-    let pak_path = pak_path.as_ref();
-    let mut out_file = match OpenOptions::new()
+    let out_file = match OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true)
@@ -202,11 +184,9 @@ pub fn pack(pak_path: impl AsRef<Path>, paths: &[PackPath], options: PackOptions
             Err(error) => return Err(Error::io_with_path(error, pak_path))
         };
 
-    let mut records = Vec::new();
-    let mut buffer = Vec::with_capacity(BUFFER_SIZE);
-    let mut writer = BufWriter::new(&mut out_file);
-
-    let mut data_size = 0u64;
+    let mut writer = PakWriter::new(BufWriter::new(out_file), options.clone())
+        .map_err(|error| error.with_path(pak_path))?
+        .with_path(pak_path);
 
     let thread_result = thread::scope::<_, Result<()>>(|scope| {
         let mut filenames = HashMap::new();
@@ -236,10 +216,13 @@ pub fn pack(pak_path: impl AsRef<Path>, paths: &[PackPath], options: PackOptions
                 path.compression_method
             };
 
-            if options.version < 2 && compression_method != COMPR_NONE {
-                return Err(Error::new("Compression is only supported startig with version 2".to_string())
-                    .with_path(&path.filename));
-            }
+            check_compression_method(&options, compression_method)
+                .map_err(|error| error.with_path(&path.filename))?;
+
+            let file_options = FileOptions {
+                compression_method,
+                ..FileOptions::from(path)
+            };
 
             let source_path: PathBuf;
             let filename = if let Some(filename) = &path.rename {
@@ -305,8 +288,7 @@ pub fn pack(pak_path: impl AsRef<Path>, paths: &[PackPath], options: PackOptions
                     match work_sender.send(Work {
                         filename,
                         file_path,
-                        path,
-                        compression_method,
+                        file_options: file_options.clone(),
                     }) {
                         Ok(()) => {}
                         Err(error) =>
@@ -319,8 +301,7 @@ pub fn pack(pak_path: impl AsRef<Path>, paths: &[PackPath], options: PackOptions
                 match work_sender.send(Work {
                     filename,
                     file_path,
-                    path,
-                    compression_method,
+                    file_options,
                 }) {
                     Ok(()) => {}
                     Err(error) =>
@@ -331,26 +312,9 @@ pub fn pack(pak_path: impl AsRef<Path>, paths: &[PackPath], options: PackOptions
 
         drop(work_sender);
 
-        let seperator = if options.null_separated { '\0' } else { '\n' };
-
         while let Ok(result) = result_receiver.recv() {
-            let (mut record, mut data) = result?;
-
-            record.move_to(options.version, data_size);
-
-            buffer.clear();
-            write_record_inline(&record, &mut buffer)?;
-
-            data.splice(0..buffer.len(), buffer.iter().cloned());
-
-            writer.write_all(&data)?;
-            data_size += data.len() as u64;
-
-            if options.verbose {
-                print!("{}{}", record.filename(), seperator);
-            }
-
-            records.push(record);
+            let (record, data) = result?;
+            writer.write_record(record, data)?;
         }
 
         drop(result_receiver);
@@ -365,87 +329,500 @@ pub fn pack(pak_path: impl AsRef<Path>, paths: &[PackPath], options: PackOptions
         Ok(result) => result?
     }
 
-    let index_offset = data_size;
-
-    writer.seek(SeekFrom::Start(index_offset))?;
-
-    let mut index_size = 0u64;
-
-    let mount_pount = options.mount_point.unwrap_or("");
+    let (_, pak) = writer.finish()?;
 
-    let mut hasher = OpenSSLSha1::new();
-
-    buffer.clear();
-
-    write_path(&mut buffer, mount_pount, options.encoding)?;
-    encode!(&mut buffer, records.len() as u32);
-    writer.write_all(&buffer)?;
-    hasher.update(&buffer);
+    Ok(pak)
+}
 
-    index_size += buffer.len() as u64;
+type WriteRecordInline = fn(&Record, &mut Vec<u8>) -> Result<()>;
 
-    let write_record = match options.variant {
+fn inline_record_writer(options: &PackOptions) -> Result<WriteRecordInline> {
+    match options.variant {
         Variant::ConanExiles => {
             if options.version != 4 {
                 return Err(Error::new(format!(
                     "Only know how to handle Conan Exile paks of version 4, but version was {}.",
-                    options.version)).
-                    with_path(pak_path));
+                    options.version)));
             }
-            Record::write_conan_exiles
+            Ok(Record::write_conan_exiles_inline)
         }
         Variant::Standard => match options.version {
-            1 => Record::write_v1,
-            2 => Record::write_v2,
-            3 => Record::write_v3,
-            // XXX: There is an unknown 32bit field after the inline(!) record information if compressed.
-            // 4 => Record::write_v3, // maybe?
-            // 5 => Record::write_v3, // maybe?
-            // 7 => Record::write_v3, // maybe?
-            _ => {
-                return Err(Error::new(
-                    format!("unsupported version: {}", options.version)).
-                    with_path(pak_path));
+            1 => Ok(Record::write_v1_inline),
+            2 => Ok(Record::write_v2_inline),
+            // The inline record of versions 4 to 7 has the same layout as in version 3.
+            // What looked like an unknown 32bit field after it is the start of the
+            // compressed data (the zlib header 0x78 0x9c).
+            // Versions 8 and up also use it, only that the compression method
+            // is an index into the compression method names in the footer.
+            3..=11 => Ok(Record::write_v3_inline),
+            _ => Err(Error::new(format!("unsupported version: {}", options.version)))
+        }
+    }
+}
+
+fn check_options(options: &PackOptions) -> Result<()> {
+    inline_record_writer(options)?;
+
+    if let Some(key) = &options.encryption_key {
+        if key.len() != 32 {
+            return Err(Error::new(format!(
+                "encryption key needs to be 32 bytes long, but was {} bytes", key.len())));
+        }
+
+        if options.version < 3 {
+            return Err(Error::new("Encryption is only supported starting with version 3".to_string()));
+        }
+
+        if options.encrypt_index_only && options.version < 4 {
+            return Err(Error::new("Index encryption is only supported starting with version 4".to_string()));
+        }
+    }
+
+    if options.compression_method != COMPR_NONE && options.codecs.by_method(options.compression_method).is_none() {
+        return Err(Error::new(
+            format!("unsupported compression method: {} ({})",
+                compression_method_name(options.compression_method), options.compression_method)));
+    }
+
+    Ok(())
+}
+
+fn check_compression_method(options: &PackOptions, compression_method: u32) -> Result<()> {
+    if options.version < 2 && compression_method != COMPR_NONE {
+        return Err(Error::new("Compression is only supported startig with version 2".to_string()));
+    }
+
+    if compression_method != COMPR_NONE {
+        let codec = match options.codecs.by_method(compression_method) {
+            Some(codec) => codec,
+            None => return Err(Error::new(format!(
+                "unsupported compression method: {} ({})",
+                compression_method_name(compression_method), compression_method)))
+        };
+
+        if options.version < PAK_COMPRESSION_METHOD_NAMES_VERSION && compression_method >= COMPR_CUSTOM {
+            return Err(Error::new(format!(
+                "{} compression is only supported starting with version {}",
+                codec.name(), PAK_COMPRESSION_METHOD_NAMES_VERSION)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Per-file options of [`PakWriter::add_file`]. Unset values fall back to
+/// the [`PackOptions`] of the writer.
+#[derive(Debug, Clone)]
+pub struct FileOptions {
+    pub compression_method: u32,
+    pub compression_block_size: Option<NonZeroU32>,
+    pub compression_level: Option<NonZeroU32>,
+    /// Seconds since the Unix epoch, only stored by version 1. Defaults to now.
+    pub timestamp: Option<u64>,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            compression_method: COMPR_DEFAULT,
+            compression_block_size: None,
+            compression_level: None,
+            timestamp: None,
+        }
+    }
+}
+
+impl From<&PackPath> for FileOptions {
+    fn from(path: &PackPath) -> Self {
+        Self {
+            compression_method: path.compression_method,
+            compression_block_size: path.compression_block_size,
+            compression_level: path.compression_level,
+            timestamp: None,
+        }
+    }
+}
+
+/// Writes a pak to any [`Write`], e.g. a `Vec<u8>` or a socket.
+///
+/// Files are written in the order they are added and the index and footer
+/// are written by [`PakWriter::finish`], so the output is never seeked.
+pub struct PakWriter<'a, W: Write> {
+    writer: W,
+    options: PackOptions<'a>,
+    path: Option<PathBuf>,
+    write_record_inline: WriteRecordInline,
+    encoder: RecordEncoder,
+    records: Vec<Record>,
+    filenames: HashSet<String>,
+    compression_methods: Vec<String>,
+    data_size: u64,
+    buffer: Vec<u8>,
+}
+
+impl<'a, W: Write> PakWriter<'a, W> {
+    pub fn new(writer: W, options: PackOptions<'a>) -> Result<Self> {
+        check_options(&options)?;
+        let write_record_inline = inline_record_writer(&options)?;
+
+        Ok(Self {
+            writer,
+            options,
+            path: None,
+            write_record_inline,
+            encoder: RecordEncoder::default(),
+            records: Vec::new(),
+            filenames: HashSet::new(),
+            compression_methods: Vec::new(),
+            data_size: 0,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+        })
+    }
+
+    /// Path of the pak that is written. It is used in error messages and
+    /// its file name seeds the path hashes of version 10 and up.
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    #[inline]
+    pub fn options(&self) -> &PackOptions<'a> {
+        &self.options
+    }
+
+    #[inline]
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Compresses, encrypts, and writes the data read from `data` until its
+    /// end. `data` can be a byte slice or any other reader.
+    pub fn add_file(&mut self, filename: &str, mut data: impl Read, file_options: &FileOptions) -> Result<()> {
+        let components = parse_pak_path(filename).collect::<Vec<_>>();
+        if components.is_empty() {
+            return Err(Error::new(format!("illegal filename: {:?}", filename)));
+        }
+        let filename = make_pak_path(components.iter());
+
+        if self.filenames.contains(&filename) {
+            return Err(Error::new(format!("{}: filename not unique in archive", filename)));
+        }
+
+        let compression_method = if file_options.compression_method == COMPR_DEFAULT {
+            self.options.compression_method
+        } else {
+            file_options.compression_method
+        };
+        check_compression_method(&self.options, compression_method)
+            .map_err(|error| error.with_path(&filename))?;
+
+        let mut buffer = Vec::new();
+        if let Err(error) = data.read_to_end(&mut buffer) {
+            return Err(Error::io_with_path(error, filename));
+        }
+        let uncompressed_size = buffer.len() as u64;
+
+        let (record, data) = self.encoder.encode(&self.options, filename.clone(), &mut Cursor::new(buffer), uncompressed_size, file_options)
+            .map_err(|error| error.with_path_if_none(&filename))?;
+
+        self.write_record(record, data)
+    }
+
+    // Writes a record as returned by RecordEncoder::encode().
+    fn write_record(&mut self, mut record: Record, mut data: Vec<u8>) -> Result<()> {
+        if !self.filenames.insert(record.filename().to_string()) {
+            return Err(Error::new(format!("{}: filename not unique in archive", record.filename())));
+        }
+
+        if self.options.version >= PAK_COMPRESSION_METHOD_NAMES_VERSION && record.compression_method() != COMPR_NONE {
+            let index = compression_method_index(&mut self.compression_methods, &self.options.codecs, record.compression_method())
+                .map_err(|error| error.with_path(record.filename()))?;
+            record.set_compression_method(index);
+        }
+
+        record.move_to(self.options.version, self.data_size);
+
+        self.buffer.clear();
+        (self.write_record_inline)(&record, &mut self.buffer)?;
+
+        data.splice(0..self.buffer.len(), self.buffer.iter().cloned());
+
+        self.writer.write_all(&data)?;
+        self.data_size += data.len() as u64;
+
+        if self.options.verbose {
+            let seperator = if self.options.null_separated { '\0' } else { '\n' };
+            print!("{}{}", record.filename(), seperator);
+        }
+
+        self.records.push(record);
+
+        Ok(())
+    }
+
+    fn error_with_path(&self, error: Error) -> Error {
+        match &self.path {
+            Some(path) => error.with_path_if_none(path),
+            None => error,
+        }
+    }
+
+    /// Writes the index and footer and returns the underlying writer and
+    /// the written pak.
+    pub fn finish(mut self) -> Result<(W, Pak)> {
+        let options = &self.options;
+        let index_offset = self.data_size;
+
+        let mount_point = options.mount_point.unwrap_or("");
+
+        // versions before 4 have no encrypted index flag
+        let index_encryption_key = if options.version >= 4 {
+            options.encryption_key.as_deref()
+        } else {
+            None
+        };
+
+        let (index_size, index_sha1) = if options.version >= PAK_PATH_HASH_INDEX_VERSION {
+            // Unreal Engine seeds the path hashes with the CRC of the lower case archive filename.
+            let pak_filename = self.path.as_deref()
+                .and_then(Path::file_name)
+                .map(|filename| filename.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let path_hash_seed = str_crc32(&pak_filename) as u64;
+
+            match write_encoded_index(&mut self.writer, path_hash_seed, index_offset, mount_point, &self.records, options, index_encryption_key) {
+                Ok(result) => result,
+                Err(error) => return Err(self.error_with_path(error)),
             }
+        } else {
+            let mut index = Vec::new();
+
+            write_path(&mut index, mount_point, options.encoding)?;
+            encode!(&mut index, self.records.len() as u32);
+
+            let write_record = match options.variant {
+                Variant::ConanExiles => Record::write_conan_exiles,
+                Variant::Standard => match options.version {
+                    1 => Record::write_v1,
+                    2 => Record::write_v2,
+                    _ => Record::write_v3,
+                }
+            };
+
+            for record in &self.records {
+                write_path(&mut index, record.filename(), options.encoding)?;
+                write_record(record, &mut index)?;
+            }
+
+            let index_sha1 = seal_index(&mut index, index_encryption_key)?;
+            self.writer.write_all(&index)?;
+
+            (index.len() as u64, index_sha1)
+        };
+
+        // encryption key GUID, all zero for unencrypted paks
+        let encryption_key_guid = if options.encryption_key.is_some() {
+            options.encryption_key_guid
+        } else {
+            0u128
+        };
+
+        encode!(&mut self.writer,
+            if options.version >= 7 {
+                encryption_key_guid,
+            }
+            if options.version >= 4 {
+                // encrypted index flag
+                index_encryption_key.is_some() as u8,
+            }
+            PAK_MAGIC,
+            options.version,
+            index_offset,
+            index_size,
+            index_sha1,
+            if options.version >= PAK_FROZEN_INDEX_VERSION && options.version < PAK_PATH_HASH_INDEX_VERSION {
+                // frozen index flag
+                0u8,
+            }
+        );
+
+        let mut compression = Vec::new();
+        if options.version >= PAK_COMPRESSION_METHOD_NAMES_VERSION {
+            compression.resize(PAK_COMPRESSION_METHOD_COUNT * PAK_COMPRESSION_METHOD_SIZE, 0);
+            for (index, name) in self.compression_methods.iter().enumerate() {
+                let offset = index * PAK_COMPRESSION_METHOD_SIZE;
+                compression[offset..offset + name.len()].copy_from_slice(name.as_bytes());
+            }
+            self.writer.write_all(&compression)?;
+        }
+        self.writer.flush()?;
+
+        let footer = Footer {
+            footer_offset: index_offset + index_size,
+            encryption_uuid: encryption_key_guid,
+            encrypted: index_encryption_key.is_some(),
+            magic: PAK_MAGIC,
+            version: options.version,
+            index_offset,
+            index_size,
+            index_sha1,
+            frozen: false,
+            compression,
+        };
+
+        let index = Index::new(
+            options
+                .mount_point
+                .map(str::to_string),
+            self.records,
+        );
+
+        let pak = Pak::new(
+            self.options.variant,
+            &footer,
+            self.options.encryption_key,
+            self.options.codecs,
+            index,
+        );
+
+        Ok((self.writer, pak))
+    }
+}
+
+fn compression_method_index(compression_methods: &mut Vec<String>, codecs: &CodecRegistry, compression_method: u32) -> Result<u32> {
+    let name = match codecs.by_method(compression_method) {
+        Some(codec) => codec.name(),
+        None => return Err(Error::new(format!(
+            "unsupported compression method: {} ({})",
+            compression_method_name(compression_method), compression_method)))
+    };
+
+    let index = if let Some(index) = compression_methods.iter().position(|other| other == name) {
+        index
+    } else {
+        if compression_methods.len() >= PAK_COMPRESSION_METHOD_COUNT {
+            return Err(Error::new(format!(
+                "too many different compression methods, maximum is {}",
+                PAK_COMPRESSION_METHOD_COUNT)));
         }
+        compression_methods.push(name.to_string());
+        compression_methods.len() - 1
     };
 
-    for record in &records {
-        buffer.clear();
-        write_path(&mut buffer, record.filename(), options.encoding)?;
-        write_record(record, &mut buffer)?;
+    // 0 means no compression, so the index is 1-based
+    Ok(index as u32 + 1)
+}
 
-        writer.write_all(&buffer)?;
-        hasher.update(&buffer);
-        index_size += buffer.len() as u64;
+// Writes the primary index of version 10 and up, followed by the path hash index
+// and the full directory index. Returns size and SHA-1 of the primary index.
+fn write_encoded_index(writer: &mut impl Write, path_hash_seed: u64, index_offset: u64, mount_point: &str, records: &[Record], options: &PackOptions, encryption_key: Option<&[u8]>) -> Result<(u64, Sha1)> {
+    let mut encoded_entries = Vec::new();
+    let mut path_hash_index = Vec::new();
+    let mut directories: BTreeMap<&str, Vec<(&str, u32)>> = BTreeMap::new();
+
+    directories.insert("/", Vec::new());
+    encode!(&mut path_hash_index, records.len() as u32);
+
+    for record in records {
+        if encoded_entries.len() > i32::MAX as usize {
+            return Err(Error::new("encoded entries are too big".to_string()));
+        }
+        let location = encoded_entries.len() as u32;
+        record.encode_entry(&mut encoded_entries)?;
+
+        let filename = record.filename();
+        encode!(&mut path_hash_index,
+            path_hash(filename, path_hash_seed, options.version),
+            location,
+        );
+
+        // directory names are relative to the mount point and end in a slash
+        let (dirname, name) = if let Some(index) = filename.rfind('/') {
+            filename.split_at(index + 1)
+        } else {
+            ("/", filename)
+        };
+        directories.entry(dirname).or_default().push((name, location));
+
+        // parent directories are listed too, even if they don't contain any files
+        let mut parent = dirname;
+        while let Some(index) = parent[..parent.len() - 1].rfind('/') {
+            parent = &parent[..index + 1];
+            directories.entry(parent).or_default();
+        }
+    }
+
+    // pruned directory index, always empty since we write the full directory index
+    encode!(&mut path_hash_index, 0u32);
+
+    let mut full_directory_index = Vec::new();
+    encode!(&mut full_directory_index, directories.len() as u32);
+    for (dirname, files) in &directories {
+        write_path(&mut full_directory_index, dirname, options.encoding)?;
+        encode!(&mut full_directory_index, files.len() as u32);
+        for (name, location) in files {
+            write_path(&mut full_directory_index, name, options.encoding)?;
+            encode!(&mut full_directory_index, *location);
+        }
     }
 
-    let index_sha1: Sha1 = hasher.finish();
+    let mut index_head = Vec::new();
+    write_path(&mut index_head, mount_point, options.encoding)?;
+    encode!(&mut index_head, records.len() as u32, path_hash_seed);
+
+    let mut index_tail = Vec::new();
+    encode!(&mut index_tail, encoded_entries.len() as u32);
+    index_tail.extend_from_slice(&encoded_entries);
+    // records that can't be encoded, but all of ours can
+    encode!(&mut index_tail, 0u32);
+
+    // has index flag + offset + size + SHA-1 for both secondary indices
+    const SECONDARY_INDEX_INFO_SIZE: usize = 4 + 8 + 8 + 20;
+    let mut index_size = (index_head.len() + 2 * SECONDARY_INDEX_INFO_SIZE + index_tail.len()) as u64;
+    if encryption_key.is_some() {
+        index_size = align(index_size, BLOCK_SIZE as u64);
+    }
 
-    encode!(&mut writer,
-        PAK_MAGIC,
-        options.version,
-        index_offset,
-        index_size,
-        index_sha1,
+    let path_hash_index_sha1 = seal_index(&mut path_hash_index, encryption_key)?;
+    let full_directory_index_sha1 = seal_index(&mut full_directory_index, encryption_key)?;
+
+    let path_hash_index_offset = index_offset + index_size;
+    let full_directory_index_offset = path_hash_index_offset + path_hash_index.len() as u64;
+
+    let mut index = index_head;
+    encode!(&mut index,
+        1u32,
+        path_hash_index_offset,
+        path_hash_index.len() as u64,
+        path_hash_index_sha1,
+        1u32,
+        full_directory_index_offset,
+        full_directory_index.len() as u64,
+        full_directory_index_sha1,
     );
-    writer.flush()?;
+    index.extend_from_slice(&index_tail);
 
-    let index = Index::new(
-        options
-            .mount_point
-            .map(str::to_string),
-        records,
-    );
+    let index_sha1 = seal_index(&mut index, encryption_key)?;
+
+    writer.write_all(&index)?;
+    writer.write_all(&path_hash_index)?;
+    writer.write_all(&full_directory_index)?;
 
-    Ok(Pak::new(
-        options.variant,
-        options.version,
-        index_offset,
-        index_size,
-        index_sha1,
-        index,
-    ))
+    Ok((index_size, index_sha1))
+}
+
+// Pads and encrypts an index blob if a key is given. Like Unreal Engine the
+// SHA-1 is computed over the padded, but not yet encrypted data.
+fn seal_index(data: &mut Vec<u8>, encryption_key: Option<&[u8]>) -> Result<Sha1> {
+    if let Some(key) = encryption_key {
+        data.resize(align(data.len() as u64, BLOCK_SIZE as u64) as usize, 0);
+        let sha1 = sha1_digest(&data[..])?;
+        encrypt(data, key)?;
+        Ok(sha1)
+    } else {
+        sha1_digest(&data[..])
+    }
 }
 
 pub fn write_path(writer: &mut impl Write, path: &str, encoding: Encoding) -> Result<()> {
@@ -503,15 +880,14 @@ pub fn write_path(writer: &mut impl Write, path: &str, encoding: Encoding) -> Re
 }
 
 #[derive(Debug)]
-struct Work<'a> {
+struct Work {
     filename: String,
     file_path: PathBuf,
-    path: &'a PackPath,
-    compression_method: u32,
+    file_options: FileOptions,
 }
 
 #[inline]
-fn write_uncompressed(data: &mut Vec<u8>, header_buffer: &mut Vec<u8>, base_header_size: u64, in_file: &mut File, uncompressed_size: u64, buffer: &mut Vec<u8>) -> Result<Sha1> {
+fn write_uncompressed(data: &mut Vec<u8>, header_buffer: &mut Vec<u8>, base_header_size: u64, in_file: &mut impl Read, uncompressed_size: u64, buffer: &mut Vec<u8>, encryption_key: Option<&[u8]>) -> Result<Sha1> {
     let mut hasher = OpenSSLSha1::new();
 
     data.write_all(&header_buffer[..base_header_size as usize])?;
@@ -539,46 +915,22 @@ fn write_uncompressed(data: &mut Vec<u8>, header_buffer: &mut Vec<u8>, base_head
         hasher.update(buffer);
     }
 
+    if let Some(key) = encryption_key {
+        // the SHA-1 covers the data as written, i.e. padded and encrypted
+        let mut payload = data.split_off(base_header_size as usize);
+        pad_and_encrypt(&mut payload, key)?;
+        data.extend_from_slice(&payload);
+        return sha1_digest(&payload[..]);
+    }
+
     Ok(hasher.finish())
 }
 
-fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_channel: Sender<Result<(Record, Vec<u8>)>>) -> Result<()> {
-    let mut buffer = vec![0u8; BUFFER_SIZE];
-    let mut out_buffer = Vec::new();
-
-    let compression_level = Compression::new(options.compression_level.get());
-    let compression_min_size = options.compression_min_size.get();
-
-    let base_header_size = match options.variant {
-        Variant::ConanExiles => {
-            if options.version != 4 {
-                return Err(Error::new(format!(
-                    "Only know how to handle Conan Exile paks of version 4, but version was {}.",
-                    options.version)));
-            }
-            CONAN_EXILE_RECORD_HEADER_SIZE
-        }
-        Variant::Standard => match options.version {
-            1 => V1_RECORD_HEADER_SIZE,
-            2 => V2_RECORD_HEADER_SIZE,
-            3 => V3_RECORD_HEADER_SIZE,
-            4 => V3_RECORD_HEADER_SIZE, // maybe?
-            5 => V3_RECORD_HEADER_SIZE, // maybe?
-            7 => V3_RECORD_HEADER_SIZE, // maybe?
-            _ => {
-                panic!("unsupported version: {}", options.version)
-            }
-        }
-    };
-    let mut header_buffer = vec![0u8; base_header_size as usize];
 
-    while let Ok(Work { filename, file_path, path, mut compression_method }) = work_channel.recv() {
-        let mut data = Vec::new();
-        let offset = 0;
-        let compression_blocks;
-        let mut compression_block_size = 0u32;
-        let mut size;
+fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_channel: Sender<Result<(Record, Vec<u8>)>>) -> Result<()> {
+    let mut encoder = RecordEncoder::default();
 
+    while let Ok(Work { filename, file_path, mut file_options }) = work_channel.recv() {
         let mut in_file = match File::open(&file_path) {
             Ok(file) => file,
             Err(error) => {
@@ -595,9 +947,7 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
             }
         };
 
-        let uncompressed_size = metadata.len();
-
-        let timestamp = if options.version == 1 {
+        if options.version == 1 {
             let created = match metadata.created() {
                 Ok(created) => created,
                 Err(error) => {
@@ -612,7 +962,87 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
                     break;
                 }
             };
-            Some(timestamp.as_secs())
+            file_options.timestamp = Some(timestamp.as_secs());
+        }
+
+        let result = encoder.encode(options, filename, &mut in_file, metadata.len(), &file_options)
+            .map_err(|error| error.with_path_if_none(&file_path));
+        let failed = result.is_err();
+
+        result_channel.send(result)?;
+
+        if failed {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn base_header_size(options: &PackOptions) -> Result<u64> {
+    match options.variant {
+        Variant::ConanExiles => {
+            if options.version != 4 {
+                return Err(Error::new(format!(
+                    "Only know how to handle Conan Exile paks of version 4, but version was {}.",
+                    options.version)));
+            }
+            Ok(CONAN_EXILE_RECORD_HEADER_SIZE)
+        }
+        Variant::Standard => match options.version {
+            1 => Ok(V1_RECORD_HEADER_SIZE),
+            2 => Ok(V2_RECORD_HEADER_SIZE),
+            3..=11 => Ok(V3_RECORD_HEADER_SIZE),
+            _ => Err(Error::new(format!("unsupported version: {}", options.version)))
+        }
+    }
+}
+
+// Buffers that are reused for all records of a thread.
+#[derive(Debug, Default)]
+struct RecordEncoder {
+    buffer: Vec<u8>,
+    out_buffer: Vec<u8>,
+    header_buffer: Vec<u8>,
+}
+
+impl RecordEncoder {
+    // Compresses and encrypts the data of a file. The returned data starts
+    // with room for the inline record header, which is filled in once the
+    // offset of the record is known.
+    fn encode(&mut self, options: &PackOptions, filename: String, in_file: &mut (impl Read + Seek), uncompressed_size: u64, file_options: &FileOptions) -> Result<(Record, Vec<u8>)> {
+        let Self { buffer, out_buffer, header_buffer } = self;
+
+        let compression_level = options.compression_level.get();
+        let compression_min_size = options.compression_min_size.get();
+        let encryption_key = if options.encrypt_index_only {
+            None
+        } else {
+            options.encryption_key.as_deref()
+        };
+
+        let base_header_size = base_header_size(options)?;
+        if header_buffer.len() < base_header_size as usize {
+            header_buffer.resize(base_header_size as usize, 0);
+        }
+
+        let mut compression_method = if file_options.compression_method == COMPR_DEFAULT {
+            options.compression_method
+        } else {
+            file_options.compression_method
+        };
+
+        let mut data = Vec::new();
+        let offset = 0;
+        let compression_blocks;
+        let mut compression_block_size = 0u32;
+        let mut size;
+
+        let timestamp = if options.version == 1 {
+            Some(file_options.timestamp.unwrap_or_else(|| SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|timestamp| timestamp.as_secs())
+                .unwrap_or(0)))
         } else {
             None
         };
@@ -623,17 +1053,17 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
             compression_method = COMPR_NONE;
         }
 
-        match compression_method {
-            self::COMPR_NONE => {
+        match (compression_method, options.codecs.by_method(compression_method)) {
+            (self::COMPR_NONE, _) => {
                 size = uncompressed_size;
                 compression_blocks = None;
-                sha1 = write_uncompressed(&mut data, &mut header_buffer, base_header_size, &mut in_file, uncompressed_size, &mut buffer)?;
+                sha1 = write_uncompressed(&mut data, header_buffer, base_header_size, in_file, uncompressed_size, buffer, encryption_key)?;
             }
-            self::COMPR_ZLIB => {
+            (_, Some(codec)) => {
                 let mut hasher = OpenSSLSha1::new();
 
-                let compression_level = if let Some(compression_level) = path.compression_level {
-                    Compression::new(compression_level.get())
+                let compression_level = if let Some(compression_level) = file_options.compression_level {
+                    compression_level.get()
                 } else {
                     compression_level
                 };
@@ -648,10 +1078,7 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
                         let buffer = &mut buffer[..uncompressed_size as usize];
                         in_file.read_exact(buffer)?;
 
-                        out_buffer.clear();
-                        let mut zlib = ZlibEncoder::new(&mut out_buffer, compression_level);
-                        zlib.write_all(&buffer)?;
-                        zlib.finish()?;
+                        codec.compress_block(buffer, compression_level, out_buffer)?;
                     }
 
                     size = out_buffer.len() as u64;
@@ -665,15 +1092,15 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
                         data.clear();
                         in_file.seek(SeekFrom::Start(0))?;
                         size = uncompressed_size;
-                        sha1 = write_uncompressed(&mut data, &mut header_buffer, base_header_size, &mut in_file, uncompressed_size, &mut buffer)?;
+                        sha1 = write_uncompressed(&mut data, header_buffer, base_header_size, in_file, uncompressed_size, buffer, encryption_key)?;
                     } else {
-                        data.write_all(&out_buffer)?;
-                        hasher.update(&out_buffer);
+                        data.write_all(out_buffer)?;
+                        hasher.update(out_buffer);
                         sha1 = hasher.finish();
                     }
                 } else {
                     size = 0u64;
-                    compression_block_size = path.compression_block_size
+                    compression_block_size = file_options.compression_block_size
                         .unwrap_or(options.compression_block_size)
                         .get();
 
@@ -703,15 +1130,16 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
                         while remaining >= compression_block_size as usize {
                             in_file.read_exact(buffer)?;
 
-                            out_buffer.clear();
-                            let mut zlib = ZlibEncoder::new(&mut out_buffer, compression_level);
-                            zlib.write_all(&buffer)?;
-                            zlib.finish()?;
-                            data.write_all(&out_buffer)?;
-                            hasher.update(&out_buffer);
-
+                            codec.compress_block(buffer, compression_level, out_buffer)?;
                             let compressed_block_size = out_buffer.len() as u64;
-                            size += compressed_block_size;
+                            if let Some(key) = encryption_key {
+                                // every block is padded and encrypted on its own
+                                pad_and_encrypt(out_buffer, key)?;
+                            }
+                            data.write_all(out_buffer)?;
+                            hasher.update(out_buffer);
+
+                            size += out_buffer.len() as u64;
 
                             remaining -= compression_block_size as usize;
                             let end_offset = start_offset + compressed_block_size;
@@ -719,22 +1147,22 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
                                 start_offset,
                                 end_offset,
                             });
-                            start_offset = end_offset;
+                            start_offset += out_buffer.len() as u64;
                         }
 
                         if remaining > 0 {
                             let buffer = &mut buffer[..remaining];
                             in_file.read_exact(buffer)?;
 
-                            out_buffer.clear();
-                            let mut zlib = ZlibEncoder::new(&mut out_buffer, compression_level);
-                            zlib.write_all(buffer)?;
-                            zlib.finish()?;
-                            data.write_all(&out_buffer)?;
-                            hasher.update(&out_buffer);
-
+                            codec.compress_block(buffer, compression_level, out_buffer)?;
                             let compressed_block_size = out_buffer.len() as u64;
-                            size += compressed_block_size;
+                            if let Some(key) = encryption_key {
+                                pad_and_encrypt(out_buffer, key)?;
+                            }
+                            data.write_all(out_buffer)?;
+                            hasher.update(out_buffer);
+
+                            size += out_buffer.len() as u64;
 
                             let end_offset = start_offset + compressed_block_size;
                             blocks.push(CompressionBlock {
@@ -753,18 +1181,18 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
                         in_file.seek(SeekFrom::Start(0))?;
                         size = uncompressed_size;
                         compression_blocks = None;
-                        sha1 = write_uncompressed(&mut data, &mut header_buffer, base_header_size, &mut in_file, uncompressed_size, &mut buffer)?;
+                        sha1 = write_uncompressed(&mut data, header_buffer, base_header_size, in_file, uncompressed_size, buffer, encryption_key)?;
                     } else {
                         compression_blocks = Some(blocks);
                         sha1 = hasher.finish();
                     }
                 }
             }
-            _ => {
-                result_channel.send(Err(Error::new(
-                    format!("{}: unsupported compression method: {} ({})",
-                        path.filename, compression_method_name(compression_method), compression_method))))?;
-                break;
+            (_, None) => {
+                return Err(Error::new(format!(
+                    "unsupported compression method: {} ({})",
+                    compression_method_name(compression_method), compression_method))
+                    .with_path(&filename));
             }
         }
 
@@ -777,12 +1205,10 @@ fn worker_proc(options: &PackOptions, work_channel: Receiver<Work>, result_chann
             timestamp,
             Some(sha1),
             compression_blocks,
-            false,
+            encryption_key.is_some(),
             compression_block_size,
         );
 
-        result_channel.send(Ok((record, data)))?;
+        Ok((record, data))
     }
-
-    Ok(())
 }
@@ -4,20 +4,34 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{convert::TryFrom, fmt::Display, num::{NonZeroU32, NonZeroU64}, path::Path, usize};
+use std::{convert::TryFrom, fmt::Display, num::{NonZeroU32, NonZeroU64}, path::Path, sync::Arc, usize};
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, BufReader};
 use log::{debug};
+use aes::BLOCK_SIZE;
+use openssl::sha::sha1;
 
 use crate::{Error, Record, Result};
+use crate::codec::{Codec, CodecRegistry};
+use crate::util::{align, format_guid};
 use crate::decode;
 use crate::decode::Decode;
 use crate::index::{Encoding, Index};
+use crate::keyring::Keyring;
+use crate::dictionary::Dictionary;
+use crate::entry::EntryReader;
+use crate::check::NULL_SHA1;
+use crate::decrypt::decrypt;
 
 pub const BUFFER_SIZE: usize = 2 * 1024 * 1024;
 
 pub const PAK_MAGIC: u32 = 0x5A6F12E1;
 pub const PAK_RELATIVE_COMPRESSION_OFFSET_VERSION: u32 = 5;
+pub const PAK_ENCRYPTION_GUID_VERSION: u32 = 7;
+pub const PAK_COMPRESSION_METHOD_NAMES_VERSION: u32 = 8;
+pub const PAK_FROZEN_INDEX_VERSION: u32 = 9;
+pub const PAK_PATH_HASH_INDEX_VERSION: u32 = 10;
+pub const PAK_FNV64_BUGFIX_VERSION: u32 = 11;
 pub const PAK_MAX_SUPPORTED_VERSION: u32 = 11;
 
 pub const DEFAULT_BLOCK_SIZE: NonZeroU32 = unsafe { NonZeroU32::new_unchecked(64 * 1024) };
@@ -26,8 +40,15 @@ pub const DEFAULT_MIN_COMPRESSION_SIZE: NonZeroU64 = unsafe { NonZeroU64::new_un
 
 pub const COMPR_NONE       : u32 = 0x00;
 pub const COMPR_ZLIB       : u32 = 0x01;
+pub const COMPR_GZIP       : u32 = 0x02;
 pub const COMPR_BIAS_MEMORY: u32 = 0x10; // I'm not sure, maybe these are just flags for zlib?
 pub const COMPR_BIAS_SPEED : u32 = 0x20;
+// Ids starting with this one have no meaning in paks before version 8. They
+// are only used to select codecs when packing version 8+ paks, where records
+// refer to the footer names instead.
+pub const COMPR_CUSTOM     : u32 = 0x100;
+// There is no legacy id for LZ4.
+pub const COMPR_LZ4        : u32 = COMPR_CUSTOM;
 
 pub const V1_RECORD_HEADER_SIZE: u64 = 56;
 pub const V2_RECORD_HEADER_SIZE: u64 = 48;
@@ -42,16 +63,27 @@ pub const PAK_COMPRESSION_METHOD_COUNT: usize = 5;
 pub const PAK_COMPRESSION_METHOD_SIZE: usize = 32;
 pub const PAK_ENCRYPTION_GUID_SIZE: usize = std::mem::size_of::<u128>();
 
-pub const COMPR_METHODS: [u32; 4] = [COMPR_NONE, COMPR_ZLIB, COMPR_BIAS_MEMORY, COMPR_BIAS_SPEED];
+// Compression method names as used in the footer of version 8 and up.
+pub const COMPR_NAME_ZLIB : &str = "Zlib";
+pub const COMPR_NAME_GZIP : &str = "Gzip";
+pub const COMPR_NAME_OODLE: &str = "Oodle";
+pub const COMPR_NAME_LZ4  : &str = "LZ4";
+
+pub const COMPR_NAMES: [&str; 4] = [COMPR_NAME_ZLIB, COMPR_NAME_GZIP, COMPR_NAME_OODLE, COMPR_NAME_LZ4];
+
+pub const COMPR_METHODS: [u32; 5] = [COMPR_NONE, COMPR_ZLIB, COMPR_GZIP, COMPR_BIAS_MEMORY, COMPR_BIAS_SPEED];
 
 pub type Sha1 = [u8; 20];
 
+/// Name of a compression method of a pak before version 8.
 pub fn compression_method_name(compression_method: u32) -> &'static str {
     match compression_method {
         COMPR_NONE => "-",
         COMPR_ZLIB => "zlib",
+        COMPR_GZIP => "gzip",
         COMPR_BIAS_MEMORY => "bias memory",
         COMPR_BIAS_SPEED  => "bias speed",
+        COMPR_LZ4         => "lz4",
         _ => "unknown",
     }
 }
@@ -105,13 +137,20 @@ impl TryFrom<&str> for Variant {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Options {
     pub variant: Variant,
     pub ignore_magic: bool,
     pub encoding: Encoding,
     pub force_version: Option<u32>,
     pub encryption_key: Option<Vec<u8>>,
+    /// Keys selected by the encryption key GUID of the footer. A matching key
+    /// takes precedence over `encryption_key`.
+    pub keyring: Arc<Keyring>,
+    pub codecs: Arc<CodecRegistry>,
+    /// Candidate paths to restore the file names of paks that only have a
+    /// path hash index.
+    pub dictionary: Arc<Dictionary>,
 }
 
 impl Default for Options {
@@ -122,21 +161,69 @@ impl Default for Options {
             encoding: Encoding::UTF8,
             force_version: None,
             encryption_key: None,
+            keyring: Arc::new(Keyring::default()),
+            codecs: Arc::new(CodecRegistry::default()),
+            dictionary: Arc::new(Dictionary::default()),
         }
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Footer {
-    footer_offset: u64,
-    encryption_uuid: u128,
-    encrypted: bool,
-    magic: u32,
-    version: u32,
-    index_offset: u64,
-    index_size: u64,
-    index_sha1: Sha1,
-    frozen: bool,
-    compression: Vec<u8>,
+    pub(crate) footer_offset: u64,
+    pub(crate) encryption_uuid: u128,
+    pub(crate) encrypted: bool,
+    pub(crate) magic: u32,
+    pub(crate) version: u32,
+    pub(crate) index_offset: u64,
+    pub(crate) index_size: u64,
+    pub(crate) index_sha1: Sha1,
+    pub(crate) frozen: bool,
+    pub(crate) compression: Vec<u8>,
+}
+
+impl Footer {
+    #[inline]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    #[inline]
+    pub fn index_offset(&self) -> u64 {
+        self.index_offset
+    }
+
+    #[inline]
+    pub fn index_size(&self) -> u64 {
+        self.index_size
+    }
+
+    #[inline]
+    pub fn index_sha1(&self) -> &Sha1 {
+        &self.index_sha1
+    }
+
+    #[inline]
+    pub fn encrypted_index(&self) -> bool {
+        self.encrypted
+    }
+
+    /// GUID of the encryption key (version 7 and up, otherwise 0).
+    #[inline]
+    pub fn encryption_guid(&self) -> u128 {
+        self.encryption_uuid
+    }
+
+    /// Frozen index flag (only version 9).
+    #[inline]
+    pub fn frozen_index(&self) -> bool {
+        self.frozen
+    }
+
+    /// Compression method names (version 8 and up).
+    pub fn compression_methods(&self) -> Vec<String> {
+        parse_compression_methods(&self.compression)
+    }
 }
 
 #[derive(Debug)]
@@ -146,6 +233,12 @@ pub struct Pak {
     index_offset: u64,
     index_size: u64,
     index_sha1: Sha1,
+    encrypted_index: bool,
+    encryption_guid: u128,
+    encryption_key: Option<Vec<u8>>,
+    frozen_index: bool,
+    compression_methods: Vec<String>,
+    codecs: Arc<CodecRegistry>,
     index: Index,
 }
 
@@ -153,18 +246,23 @@ impl Pak {
     #[inline]
     pub(crate) fn new(
         variant: Variant,
-        version: u32,
-        index_offset: u64,
-        index_size: u64,
-        index_sha1: Sha1,
+        footer: &Footer,
+        encryption_key: Option<Vec<u8>>,
+        codecs: Arc<CodecRegistry>,
         index: Index,
     ) -> Self {
         Self {
             variant,
-            version,
-            index_offset,
-            index_size,
-            index_sha1,
+            version: footer.version,
+            index_offset: footer.index_offset,
+            index_size: footer.index_size,
+            index_sha1: footer.index_sha1,
+            encrypted_index: footer.encrypted,
+            encryption_guid: footer.encryption_uuid,
+            encryption_key,
+            frozen_index: footer.frozen,
+            compression_methods: parse_compression_methods(&footer.compression),
+            codecs,
             index,
         }
     }
@@ -189,9 +287,68 @@ impl Pak {
     }
 
     pub fn from_reader<R>(reader: &mut R, options: Options) -> Result<Pak>
+    where R: Read, R: Seek {
+        let footer = Self::read_footer(reader, &options)?;
+        let variant = options.variant;
+
+        // A frozen index is a memory image of the engine's data structures and
+        // depends on the engine build that wrote it.
+        if footer.frozen {
+            return Err(Error::new(format!(
+                "frozen index is not supported: the index at offset {} ({} bytes) of this version {} pak \
+                 is a memory image of the engine's internal data structures, not a serialized index",
+                footer.index_offset, footer.index_size, footer.version)));
+        }
+
+        let encryption_key = match options.keyring.get(footer.encryption_uuid) {
+            Some(key) => Some(key.to_vec()),
+            None => options.encryption_key,
+        };
+
+        if footer.encrypted && encryption_key.is_none() {
+            return Err(Error::new(if footer.version >= PAK_ENCRYPTION_GUID_VERSION {
+                format!("index is encrypted, but no encryption key for GUID {} was given",
+                    format_guid(footer.encryption_uuid))
+            } else {
+                "index is encrypted, but no encryption key was given".to_string()
+            }));
+        }
+
+        if footer.encrypted {
+            if let Some(key) = &encryption_key {
+                Self::verify_encryption_key(reader, &footer, key)?;
+            }
+        }
+
+        reader.seek(SeekFrom::Start(footer.index_offset))?;
+
+        let index = Index::read(
+            reader,
+            footer.index_size as usize,
+            footer.version,
+            variant,
+            options.encoding,
+            match footer.encrypted {
+                true => encryption_key.clone(),
+                false => None,
+            },
+            &options.dictionary,
+        )?;
+
+        let pos = reader.seek(SeekFrom::Current(0))?;
+        if pos > footer.footer_offset {
+            return Err(Error::new("index bleeds into footer".to_owned()));
+        }
+
+        Ok(Self::new(variant, &footer, encryption_key, options.codecs, index))
+    }
+
+    /// Reads only the footer, e.g. to show information about a pak whose
+    /// index can't be read.
+    pub fn read_footer<R>(reader: &mut R, options: &Options) -> Result<Footer>
     where R: Read, R: Seek {
         let footer: Footer;
-        
+
         if let Some(force_version) = options.force_version {
             footer = Self::decode_footer(reader, force_version)?;
             if !options.ignore_magic && footer.magic != 0x5A6F12E1 {
@@ -211,41 +368,44 @@ impl Pak {
             }
         }
 
-        let variant = options.variant;
-
         if footer.index_offset + footer.index_size > footer.footer_offset {
             return Err(Error::new(format!(
                 "illegal index offset/size: index_offset ({}) + index_size ({}) > footer_offset ({})",
                 footer.index_offset, footer.index_size, footer.footer_offset)));
         }
 
-        reader.seek(SeekFrom::Start(footer.index_offset))?;
+        Ok(footer)
+    }
 
-        let index = Index::read(
-            reader,
-            footer.index_size as usize,
-            footer.version,
-            variant,
-            options.encoding,
-            match footer.encrypted {
-                true => options.encryption_key,
-                false => None,
-            },
-        )?;
+    // Decrypting with the wrong key only produces garbage, so compare the hash of
+    // the decrypted index first. The hash is over the padded plain text.
+    fn verify_encryption_key<R>(reader: &mut R, footer: &Footer, key: &[u8]) -> Result<()>
+    where R: Read, R: Seek {
+        if key.len() != 32 {
+            return Err(Error::new(format!(
+                "encryption key needs to be 32 bytes long, but is {} bytes long", key.len())));
+        }
 
-        let pos = reader.seek(SeekFrom::Current(0))?;
-        if pos > footer.footer_offset {
-            return Err(Error::new("index bleeds into footer".to_owned()));
+        if align(footer.index_size, BLOCK_SIZE as u64) != footer.index_size {
+            return Err(Error::new(format!(
+                "encrypted index size is not a multiple of {}: {}", BLOCK_SIZE, footer.index_size)));
         }
 
-        Ok(Self {
-            variant,
-            version: footer.version,
-            index_offset: footer.index_offset,
-            index_size: footer.index_size,
-            index_sha1: footer.index_sha1,
-            index,
-        })
+        if footer.index_sha1 == NULL_SHA1 {
+            return Ok(());
+        }
+
+        reader.seek(SeekFrom::Start(footer.index_offset))?;
+        let mut data = vec![0u8; footer.index_size as usize];
+        reader.read_exact(&mut data)?;
+        decrypt(&mut data, key);
+
+        if sha1(&data) != footer.index_sha1 {
+            return Err(Error::new(
+                "encryption key doesn't match the archive (index checksum missmatch after decryption)".to_string()));
+        }
+
+        Ok(())
     }
 
     #[inline]
@@ -273,6 +433,106 @@ impl Pak {
         &self.index_sha1
     }
 
+    #[inline]
+    pub fn encrypted_index(&self) -> bool {
+        self.encrypted_index
+    }
+
+    /// GUID of the encryption key from the footer (version 7 and up, otherwise 0).
+    #[inline]
+    pub fn encryption_guid(&self) -> u128 {
+        self.encryption_guid
+    }
+
+    /// The key used to read this pak, either from the keyring or the one
+    /// given via [`Options::encryption_key`].
+    #[inline]
+    pub fn encryption_key(&self) -> Option<&[u8]> {
+        self.encryption_key.as_deref()
+    }
+
+    /// Reads the primary index or a secondary index, decrypted if the index
+    /// is encrypted.
+    pub fn read_index_data<R>(&self, reader: &mut R, offset: u64, size: u64) -> Result<Vec<u8>>
+    where R: Read, R: Seek {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0u8; size as usize];
+        reader.read_exact(&mut data)?;
+
+        if self.encrypted_index {
+            if let Some(key) = &self.encryption_key {
+                decrypt(&mut data, key);
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Opens the data of a record for reading. The data is decompressed and
+    /// decrypted (using [`Pak::encryption_key`]) as it is read. `reader` has
+    /// to read the same file as this pak.
+    #[inline]
+    pub fn open_entry<R>(&self, reader: R, record: &Record) -> Result<EntryReader<'_, R>>
+    where R: Read, R: Seek {
+        EntryReader::new(self, reader, record)
+    }
+
+    /// Frozen index flag of the footer (only version 9).
+    #[inline]
+    pub fn frozen_index(&self) -> bool {
+        self.frozen_index
+    }
+
+    /// Compression method names from the footer (version 8 and up).
+    #[inline]
+    pub fn compression_methods(&self) -> &[String] {
+        &self.compression_methods
+    }
+
+    /// Name of the compression method of a record of this pak.
+    ///
+    /// From version 8 on the compression method of a record is a 1-based index
+    /// into the compression method names of the footer.
+    pub fn compression_method_name(&self, compression_method: u32) -> &str {
+        if self.version < PAK_COMPRESSION_METHOD_NAMES_VERSION {
+            let name = compression_method_name(compression_method);
+            if name == "unknown" {
+                if let Some(codec) = self.codecs.by_method(compression_method) {
+                    return codec.name();
+                }
+            }
+            return name;
+        }
+
+        if compression_method == COMPR_NONE {
+            return compression_method_name(COMPR_NONE);
+        }
+
+        self.compression_methods
+            .get(compression_method as usize - 1)
+            .map(String::as_str)
+            .unwrap_or("unknown")
+    }
+
+    #[inline]
+    pub fn codecs(&self) -> &CodecRegistry {
+        &self.codecs
+    }
+
+    /// Codec needed to decompress a record of this pak, or `None` if the
+    /// record is not compressed or no codec is registered for its method.
+    pub fn codec(&self, compression_method: u32) -> Option<&Arc<dyn Codec>> {
+        if compression_method == COMPR_NONE {
+            return None;
+        }
+
+        if self.version < PAK_COMPRESSION_METHOD_NAMES_VERSION {
+            self.codecs.by_method(compression_method)
+        } else {
+            self.codecs.by_name(self.compression_method_name(compression_method))
+        }
+    }
+
     #[inline]
     pub fn index(&self) -> &Index {
         &self.index
@@ -290,7 +550,12 @@ impl Pak {
                 if version != 4 {
                     panic!("unsupported Conan Exile pak version: {}", version)
                 }
-                CONAN_EXILE_RECORD_HEADER_SIZE
+                let mut size: u64 = CONAN_EXILE_RECORD_HEADER_SIZE;
+
+                if let Some(blocks) = &record.compression_blocks() {
+                    size += blocks.len() as u64 * COMPRESSION_BLOCK_HEADER_SIZE + 4;
+                }
+                size
             }
             Variant::Standard => match version {
                 1 => V1_RECORD_HEADER_SIZE,
@@ -565,3 +830,21 @@ impl Pak {
         }
     }
 }
+
+fn parse_compression_methods(data: &[u8]) -> Vec<String> {
+    let mut compression_methods = Vec::new();
+    for slot in data.chunks(PAK_COMPRESSION_METHOD_SIZE) {
+        let len = slot.iter().position(|&byte| byte == 0).unwrap_or(slot.len());
+        if len == 0 {
+            break;
+        }
+        let name = String::from_utf8_lossy(&slot[..len]);
+        // use our spelling for known methods so they can be easily compared
+        let name = COMPR_NAMES.iter()
+            .find(|known| known.eq_ignore_ascii_case(&name))
+            .map(|known| known.to_string())
+            .unwrap_or_else(|| name.into_owned());
+        compression_methods.push(name);
+    }
+    compression_methods
+}
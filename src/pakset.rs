@@ -0,0 +1,305 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Several paks layered the way Unreal Engine mounts them, e.g. a game's
+//! `pakchunk0-WindowsNoEditor.pak` with its `_P` patch paks. If a file is in
+//! more than one pak the record of the pak with the highest priority wins.
+
+use std::{collections::BTreeMap, fs::File, path::{Path, PathBuf}};
+
+use crate::{Error, Filter, Pak, Record, Result};
+use crate::pak::Options;
+use crate::unpack::{UnpackOptions, unpack_iter};
+use crate::util::{make_pak_path, parse_pak_path};
+use crate::walkdir::walkdir;
+
+/// Priority of a pak in a [`PakSet`]. Fields are compared in order and the
+/// pak with the greater priority overrides the other. Paks of equal priority
+/// are ordered by their path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PakPriority {
+    /// 0 for normal paks, 1 for `*_P.pak` and N + 1 for `*_N_P.pak`, like
+    /// the chunk version number of the engine.
+    pub patch: u32,
+
+    /// N of `pakchunkN*.pak`, otherwise 0.
+    pub chunk: u32,
+
+    /// Explicitly given priority.
+    pub priority: i32,
+}
+
+impl PakPriority {
+    pub fn from_filename(filename: &str, priority: i32) -> Self {
+        let stem = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+        let stem = strip_suffix_ignore_case(stem, ".pak").unwrap_or(stem);
+
+        let patch = if let Some(name) = strip_suffix_ignore_case(stem, "_p") {
+            // the first patch still needs to override the base pak
+            name.rsplit_once('_')
+                .and_then(|(_, version)| version.parse::<u32>().ok())
+                .filter(|&version| version >= 1)
+                .map_or(1, |version| version.saturating_add(1))
+        } else {
+            0
+        };
+
+        let chunk = match stem.get(..8) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("pakchunk") => {
+                let number = &stem[8..];
+                let end = number.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(number.len());
+                number[..end].parse().unwrap_or(0)
+            }
+            _ => 0
+        };
+
+        Self { patch, chunk, priority }
+    }
+}
+
+#[derive(Debug)]
+pub struct PakLayer {
+    path: PathBuf,
+    pak: Pak,
+    priority: PakPriority,
+}
+
+impl PakLayer {
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    pub fn pak(&self) -> &Pak {
+        &self.pak
+    }
+
+    #[inline]
+    pub fn priority(&self) -> PakPriority {
+        self.priority
+    }
+}
+
+/// A file of the merged view of a [`PakSet`].
+#[derive(Debug, Clone, Copy)]
+pub struct PakSetEntry<'a> {
+    path: &'a str,
+    layer_index: usize,
+    layer: &'a PakLayer,
+    record: &'a Record,
+}
+
+impl<'a> PakSetEntry<'a> {
+    /// Mount point and filename of the record joined.
+    #[inline]
+    pub fn path(&self) -> &'a str {
+        self.path
+    }
+
+    /// The pak the record comes from.
+    #[inline]
+    pub fn layer(&self) -> &'a PakLayer {
+        self.layer
+    }
+
+    /// Index of the pak in [`PakSet::layers`].
+    #[inline]
+    pub fn layer_index(&self) -> usize {
+        self.layer_index
+    }
+
+    #[inline]
+    pub fn record(&self) -> &'a Record {
+        self.record
+    }
+}
+
+impl AsRef<Record> for PakSetEntry<'_> {
+    #[inline]
+    fn as_ref(&self) -> &Record {
+        self.record
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PakSet {
+    // sorted from lowest to highest priority
+    layers: Vec<PakLayer>,
+    // path -> (layer index, record index)
+    files: BTreeMap<String, (usize, usize)>,
+}
+
+impl PakSet {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads all `*.pak` files in `dir` and its sub-directories.
+    pub fn from_dir(dir: impl AsRef<Path>, options: Options) -> Result<Self> {
+        let mut set = Self::new();
+        set.insert_dir(dir, options, 0)?;
+        Ok(set)
+    }
+
+    /// Adds all `*.pak` files in `dir` and its sub-directories with the
+    /// same explicit `priority`.
+    pub fn insert_dir(&mut self, dir: impl AsRef<Path>, options: Options, priority: i32) -> Result<()> {
+        let dir = dir.as_ref();
+        let layer_count = self.layers.len();
+
+        for entry in walkdir(dir).map_err(|error| Error::io_with_path(error, dir))? {
+            let path = entry.map_err(|error| Error::io_with_path(error, dir))?.path();
+            let is_pak = matches!(path.extension(), Some(ext) if ext.eq_ignore_ascii_case("pak"));
+
+            if is_pak {
+                let pak = Pak::from_path(&path, options.clone())?;
+                self.push(path, pak, priority);
+            }
+        }
+
+        if self.layers.len() == layer_count {
+            return Err(Error::new("no pak files found".to_string()).with_path(dir));
+        }
+
+        self.update();
+        Ok(())
+    }
+
+    /// Adds a pak. Its priority is derived from the filename of `path` and
+    /// `priority` is only used among paks of the same patch level and chunk.
+    pub fn insert(&mut self, path: impl AsRef<Path>, pak: Pak, priority: i32) {
+        self.push(path.as_ref().to_path_buf(), pak, priority);
+        self.update();
+    }
+
+    fn push(&mut self, path: PathBuf, pak: Pak, priority: i32) {
+        let filename = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let priority = PakPriority::from_filename(&filename, priority);
+        self.layers.push(PakLayer { path, pak, priority });
+    }
+
+    fn update(&mut self) {
+        self.layers.sort_by(|a, b| a.priority.cmp(&b.priority).then_with(|| a.path.cmp(&b.path)));
+
+        self.files.clear();
+        for (layer_index, layer) in self.layers.iter().enumerate() {
+            let mount_point = mount_point_components(layer.pak.index().mount_point());
+            for (record_index, record) in layer.pak.index().records().iter().enumerate() {
+                self.files.insert(join_path(&mount_point, record.filename()), (layer_index, record_index));
+            }
+        }
+    }
+
+    /// All paks from lowest to highest priority.
+    #[inline]
+    pub fn layers(&self) -> &[PakLayer] {
+        &self.layers
+    }
+
+    /// Number of files in the merged view.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The winning record of `path`.
+    pub fn get(&self, path: &str) -> Option<PakSetEntry<'_>> {
+        let path = make_pak_path(parse_pak_path(path));
+        self.files.get_key_value(&path).map(|(path, &index)| self.entry(path, index))
+    }
+
+    /// The winning records of all files, sorted by path.
+    pub fn entries(&self) -> impl Iterator<Item=PakSetEntry<'_>> {
+        self.files.iter().map(move |(path, &index)| self.entry(path, index))
+    }
+
+    #[inline]
+    fn entry<'a>(&'a self, path: &'a str, (layer_index, record_index): (usize, usize)) -> PakSetEntry<'a> {
+        let layer = &self.layers[layer_index];
+        PakSetEntry {
+            path,
+            layer_index,
+            layer,
+            record: &layer.pak.index().records()[record_index],
+        }
+    }
+
+    /// Unpacks the winning records. Files are written to their path in the
+    /// merged view, i.e. below the mount point of their pak.
+    pub fn unpack(&self, outdir: impl AsRef<Path>, options: UnpackOptions) -> Result<()> {
+        let outdir = outdir.as_ref();
+        let mut layer_records: Vec<Vec<&Record>> = vec![Vec::new(); self.layers.len()];
+
+        if let Some(paths) = options.paths {
+            let mut filter: Filter = paths.into();
+            for (path, &(layer_index, record_index)) in &self.files {
+                if filter.visit(path) {
+                    layer_records[layer_index].push(&self.layers[layer_index].pak.index().records()[record_index]);
+                }
+            }
+            filter.assert_all_visited()?;
+        } else {
+            for &(layer_index, record_index) in self.files.values() {
+                layer_records[layer_index].push(&self.layers[layer_index].pak.index().records()[record_index]);
+            }
+        }
+
+        for (layer, records) in self.layers.iter().zip(layer_records) {
+            if records.is_empty() {
+                continue;
+            }
+
+            let file = match File::open(&layer.path) {
+                Ok(file) => file,
+                Err(error) => return Err(Error::io_with_path(error, &layer.path)),
+            };
+
+            let mut layer_outdir = outdir.to_path_buf();
+            for component in mount_point_components(layer.pak.index().mount_point()) {
+                layer_outdir.push(component);
+            }
+
+            unpack_iter(&layer.pak, &file, &layer_outdir, &options, records.into_iter())
+                .map_err(|error| error.with_path_if_none(&layer.path))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn strip_suffix_ignore_case<'a>(value: &'a str, suffix: &str) -> Option<&'a str> {
+    let index = value.len().checked_sub(suffix.len())?;
+    match value.get(index..) {
+        Some(tail) if tail.eq_ignore_ascii_case(suffix) => Some(&value[..index]),
+        _ => None
+    }
+}
+
+#[inline]
+pub(crate) fn join_path(mount_point: &[&str], filename: &str) -> String {
+    make_pak_path(mount_point.iter().cloned().chain(parse_pak_path(filename)))
+}
+
+// Mount points are relative to the engine's binary directory, so leading
+// ../ are dropped. The usual ../../../ is the root directory of the game.
+pub(crate) fn mount_point_components(mount_point: Option<&str>) -> Vec<&str> {
+    let mut components = Vec::new();
+    for component in mount_point.into_iter().flat_map(parse_pak_path) {
+        match component {
+            "." => {}
+            ".." => { components.pop(); }
+            _ => components.push(component),
+        }
+    }
+    components
+}
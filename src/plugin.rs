@@ -0,0 +1,150 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Codecs loaded from shared libraries at runtime.
+//!
+//! A codec plugin exports these C functions:
+//!
+//! ```c
+//! // Name as written into the footer of version 8+ paks, e.g. "Oodle".
+//! const char *u4pak_codec_name(void);
+//!
+//! // Returns the size of the compressed data or a negative value on error.
+//! // If the returned size is bigger than output_capacity nothing was written
+//! // and the function is called again with a big enough buffer.
+//! ptrdiff_t u4pak_codec_compress_block(
+//!     const uint8_t *input, size_t input_size,
+//!     uint8_t *output, size_t output_capacity,
+//!     uint32_t level);
+//!
+//! // Decompresses into exactly output_size bytes (the uncompressed size of
+//! // the block). Returns the number of bytes written or a negative value on
+//! // error.
+//! ptrdiff_t u4pak_codec_decompress_block(
+//!     const uint8_t *input, size_t input_size,
+//!     uint8_t *output, size_t output_size);
+//! ```
+//!
+//! Plugin codecs don't have a compression method id in paks before version
+//! 8, so they can only be used with version 8 and up.
+
+use std::{ffi::CStr, os::raw::c_char, path::Path};
+
+use libloading::Library;
+
+use crate::{Error, Result, codec::{Codec, CodecRegistry}};
+
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type CompressBlockFn = unsafe extern "C" fn(*const u8, usize, *mut u8, usize, u32) -> isize;
+type DecompressBlockFn = unsafe extern "C" fn(*const u8, usize, *mut u8, usize) -> isize;
+
+#[derive(Debug)]
+pub struct PluginCodec {
+    name: String,
+    method: u32,
+    compress_block: CompressBlockFn,
+    decompress_block: DecompressBlockFn,
+    // keeps the function pointers above valid
+    _library: Library,
+}
+
+impl PluginCodec {
+    /// Loads a codec plugin. `method` is the id used to select it when packing.
+    pub fn load(path: impl AsRef<Path>, method: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let map_error = |error: libloading::Error| Error::new(error.to_string()).with_path(path);
+
+        unsafe {
+            let library = Library::new(path).map_err(map_error)?;
+
+            let name_fn = *library.get::<NameFn>(b"u4pak_codec_name\0").map_err(map_error)?;
+            let compress_block = *library.get::<CompressBlockFn>(b"u4pak_codec_compress_block\0").map_err(map_error)?;
+            let decompress_block = *library.get::<DecompressBlockFn>(b"u4pak_codec_decompress_block\0").map_err(map_error)?;
+
+            let name_ptr = name_fn();
+            if name_ptr.is_null() {
+                return Err(Error::new("codec plugin returned no name".to_string()).with_path(path));
+            }
+
+            let name = match CStr::from_ptr(name_ptr).to_str() {
+                Ok(name) => name.to_string(),
+                Err(error) => return Err(Error::new(format!(
+                    "codec plugin name is not valid UTF-8: {}", error)).with_path(path))
+            };
+
+            if name.is_empty() || name.len() >= crate::pak::PAK_COMPRESSION_METHOD_SIZE {
+                return Err(Error::new(format!(
+                    "codec plugin name must be 1 to {} bytes long: {:?}",
+                    crate::pak::PAK_COMPRESSION_METHOD_SIZE - 1, name)).with_path(path));
+            }
+
+            Ok(Self {
+                name,
+                method,
+                compress_block,
+                decompress_block,
+                _library: library,
+            })
+        }
+    }
+}
+
+impl Codec for PluginCodec {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn method(&self) -> u32 {
+        self.method
+    }
+
+    fn compress_block(&self, input: &[u8], level: u32, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        output.resize(input.len(), 0);
+
+        loop {
+            let size = unsafe {
+                (self.compress_block)(input.as_ptr(), input.len(), output.as_mut_ptr(), output.len(), level)
+            };
+
+            if size < 0 {
+                return Err(Error::new(format!("{} compression error: {}", self.name, size)));
+            }
+
+            let size = size as usize;
+            if size <= output.len() {
+                output.truncate(size);
+                return Ok(());
+            }
+
+            output.resize(size, 0);
+        }
+    }
+
+    fn decompress_block(&self, input: &[u8], uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        output.resize(uncompressed_size, 0);
+
+        let size = unsafe {
+            (self.decompress_block)(input.as_ptr(), input.len(), output.as_mut_ptr(), output.len())
+        };
+
+        if size < 0 || size as usize > uncompressed_size {
+            return Err(Error::new(format!("{} decompression error: {}", self.name, size)));
+        }
+
+        output.truncate(size as usize);
+        Ok(())
+    }
+}
+
+/// Loads a codec plugin and registers it with the next unused custom
+/// compression method id. Returns that id.
+pub fn load_codec_plugin(codecs: &mut CodecRegistry, path: impl AsRef<Path>) -> Result<u32> {
+    let method = codecs.unused_custom_method();
+    codecs.register(PluginCodec::load(path, method)?);
+    Ok(method)
+}
@@ -0,0 +1,202 @@
+// This file is part of rust-u4pak.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Positional reads that don't change any cursor, so one source can be
+//! shared by all worker threads of unpack and check.
+
+use std::{fs::File, io::{ErrorKind, Read, Seek, SeekFrom}, sync::Arc};
+
+use memmap2::Mmap;
+
+pub trait ReadAt {
+    /// Reads up to `buf.len()` bytes at `offset` and returns how many bytes
+    /// were read. Returns 0 at the end of the data.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize>;
+
+    /// Size of the whole data.
+    fn size(&self) -> std::io::Result<u64>;
+
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(buf, offset) {
+                Ok(0) => return Err(std::io::Error::new(ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer")),
+                Ok(count) => {
+                    buf = &mut buf[count..];
+                    offset += count as u64;
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for File {
+    #[cfg(target_family="unix")]
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+
+    // moves the file cursor, but nothing here relies on it
+    #[cfg(target_family="windows")]
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        if offset >= self.len() as u64 {
+            return Ok(0);
+        }
+        let data = &self[offset as usize..];
+        let count = buf.len().min(data.len());
+        buf[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        self[..].read_at(buf, offset)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl ReadAt for Mmap {
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        self[..].read_at(buf, offset)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &mut T {
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Box<T> {
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    #[inline]
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    #[inline]
+    fn size(&self) -> std::io::Result<u64> {
+        (**self).size()
+    }
+}
+
+/// [`Read`] and [`Seek`] on top of a [`ReadAt`], e.g. for [`crate::Pak::from_reader`].
+/// Each cursor has its own position, so several can read the same source.
+#[derive(Debug, Clone)]
+pub struct ReadAtCursor<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: ReadAt> ReadAtCursor<R> {
+    #[inline]
+    pub fn new(inner: R) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: ReadAt> Read for ReadAtCursor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read_at(buf, self.position)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: ReadAt> Seek for ReadAtCursor<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset)   => offset as i128,
+            SeekFrom::End(offset)     => self.inner.size()? as i128 + offset as i128,
+            SeekFrom::Current(offset) => self.position as i128 + offset as i128,
+        };
+
+        if position < 0 || position > u64::MAX as i128 {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position"));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
@@ -13,7 +13,7 @@ use crate::decode;
 use crate::decode::Decode;
 use crate::encode;
 use crate::encode::Encode;
-use crate::pak::V3_RECORD_HEADER_SIZE;
+use crate::pak::{PAK_RELATIVE_COMPRESSION_OFFSET_VERSION, V3_RECORD_HEADER_SIZE};
 use crate::util::align;
 
 macro_rules! cmp_record_field {
@@ -259,7 +259,8 @@ impl Record {
         }
 
         if compression_block_count > 0 {
-            if uncompressed_size <= 0xffff {
+            // Like Unreal Engine: a single block always spans the whole file.
+            if compression_block_count == 1 {
                 compression_block_size = uncompressed_size as u32;
             };
 
@@ -296,6 +297,95 @@ impl Record {
         Ok(Self::new(filename, offset, size, uncompressed_size, compression_method, None, None, compression_blocks, encrypted, compression_block_size))
     }
 
+    pub fn encode_entry(&self, writer: &mut impl Write) -> Result<()> {
+        // see decode_entry() for the bitfield layout
+        let offset_32bit = self.offset <= u32::MAX as u64;
+        let uncompressed_size_32bit = self.uncompressed_size <= u32::MAX as u64;
+        let size_32bit = self.size <= u32::MAX as u64;
+
+        let empty = vec![];
+        let blocks = self.compression_blocks.as_ref().unwrap_or(&empty);
+
+        if self.compression_method > 0x3f {
+            return Err(Error::new(format!(
+                "compression method index {} cannot be encoded, maximum is {}",
+                self.compression_method, 0x3f)).
+                with_path(&self.filename));
+        }
+
+        if blocks.len() > 0xffff {
+            return Err(Error::new(format!(
+                "too many compression blocks: {}, maximum is {}",
+                blocks.len(), 0xffff)).
+                with_path(&self.filename));
+        }
+
+        // A single block always spans the whole file, so the block size needs not be encoded.
+        let block_size_bits = if blocks.len() > 1 {
+            let bits = self.compression_block_size >> 11;
+            // 0x3f is used by newer engine versions to signal an extra 32bit block size field.
+            if bits << 11 != self.compression_block_size || bits >= 0x3f {
+                return Err(Error::new(format!(
+                    "compression block size {} cannot be encoded, it needs to be a multiple of 2048 and less than {}",
+                    self.compression_block_size, 0x3f << 11)).
+                    with_path(&self.filename));
+            }
+            bits
+        } else {
+            0
+        };
+
+        let bitfield: u32 =
+            if offset_32bit { 1 << 31 } else { 0 } |
+            if uncompressed_size_32bit { 1 << 30 } else { 0 } |
+            if size_32bit { 1 << 29 } else { 0 } |
+            self.compression_method << 23 |
+            if self.encrypted { 1 << 22 } else { 0 } |
+            (blocks.len() as u32) << 6 |
+            block_size_bits;
+
+        encode!(writer, bitfield);
+
+        if offset_32bit {
+            encode!(writer, self.offset as u32);
+        } else {
+            encode!(writer, self.offset);
+        }
+
+        if uncompressed_size_32bit {
+            encode!(writer, self.uncompressed_size as u32);
+        } else {
+            encode!(writer, self.uncompressed_size);
+        }
+
+        if self.compression_method != COMPR_NONE {
+            if size_32bit {
+                encode!(writer, self.size as u32);
+            } else {
+                encode!(writer, self.size);
+            }
+        }
+
+        // Blocks are stored back to back directly after the inline record, so only
+        // their sizes are needed. Not even that for a single unencrypted block.
+        if blocks.len() > 1 || (blocks.len() == 1 && self.encrypted) {
+            for block in blocks {
+                encode!(writer, (block.end_offset - block.start_offset) as u32);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The record as it is read back from an encoded index entry (version 10
+    /// and up), i.e. without SHA-1 hash and with the compression blocks and
+    /// block size as derived by `decode_entry()`.
+    pub fn to_encoded(&self) -> Result<Record> {
+        let mut buffer = Vec::new();
+        self.encode_entry(&mut buffer)?;
+        Record::decode_entry(&mut &buffer[..], self.filename.clone())
+    }
+
     pub fn read_conan_exiles(reader: &mut impl Read, filename: String) -> Result<Record> {
         decode!(reader,
             offset: u64,
@@ -434,12 +524,16 @@ impl Record {
             }
             self.encrypted as u8,
             self.compression_block_size,
-            // there are suppodes to be 20 more bytes of something that I don't know:
-            NULL_SHA1,
+            0u32,
         );
         Ok(())
     }
 
+    #[inline]
+    pub(crate) fn set_compression_method(&mut self, compression_method: u32) {
+        self.compression_method = compression_method;
+    }
+
     pub fn same_metadata(&self, other: &Record) -> bool {
         // compare all metadata except for the filename
         // data records always have offset == 0 it seems, so skip that
@@ -478,7 +572,7 @@ impl Record {
     }
 
     pub(crate) fn move_to(&mut self, version: u32, new_offset: u64) {
-        if version < 7 {
+        if version < PAK_RELATIVE_COMPRESSION_OFFSET_VERSION {
             if let Some(blocks) = &mut self.compression_blocks {
                 for block in blocks {
                     block.start_offset = (block.start_offset - self.offset) + new_offset;
@@ -148,6 +148,15 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error { path: None, error_type: ErrorType::IO(error) } => error,
+            error => std::io::Error::new(std::io::ErrorKind::Other, error.to_string()),
+        }
+    }
+}
+
 impl From<std::string::FromUtf8Error> for Error {
     fn from(error: std::string::FromUtf8Error) -> Self {
         Error::new(format!("UTF-8 conversion error: {}", error))
@@ -4,21 +4,15 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{fs::OpenOptions, io::{BufWriter, Read, Seek, SeekFrom, Write}, num::NonZeroUsize, path::{Path, PathBuf}};
-use std::fs::File;
+use std::{collections::HashMap, fs::OpenOptions, io::{BufWriter, Write}, num::NonZeroUsize, path::{Path, PathBuf}};
 
 use crossbeam_channel::{Receiver, Sender, unbounded};
 use crossbeam_utils::thread;
-use flate2::bufread::ZlibDecoder;
-use aes::BLOCK_SIZE;
 
-use crate::util::align;
-use crate::decrypt::decrypt;
-
-use crate::{Error, Result, Pak, pak::{self, COMPR_NONE, PAK_RELATIVE_COMPRESSION_OFFSET_VERSION, Variant, compression_method_name}, util::parse_pak_path};
+use crate::{EntryReader, Error, Result, Pak, pak::{BUFFER_SIZE, COMPR_BIAS_MEMORY, COMPR_BIAS_SPEED, COMPR_NONE, PAK_COMPRESSION_METHOD_NAMES_VERSION}, util::parse_pak_path};
 use crate::Record;
 use crate::Filter;
-use crate::reopen::Reopen;
+use crate::{ReadAt, ReadAtCursor};
 use log::{debug};
 
 #[derive(Debug)]
@@ -28,6 +22,8 @@ pub struct UnpackOptions<'a> {
     pub null_separated: bool,
     pub paths: Option<&'a [&'a str]>,
     pub thread_count: NonZeroUsize,
+    /// Key used to decrypt encrypted records. Defaults to the key that was
+    /// used to read the index of the pak.
     pub encryption_key: Option<Vec<u8>>,
 }
 
@@ -45,23 +41,30 @@ impl Default for UnpackOptions<'_> {
 }
 
 #[inline]
-fn unpack_iter<'a>(pak: &Pak, in_file: &mut File, outdir: &Path, options: &'a UnpackOptions<'a>, records_iter: impl Iterator<Item=&'a Record>) -> Result<()> {
-    let version = pak.version();
-    let variant = pak.variant();
-
+pub(crate) fn unpack_iter<'a, R>(pak: &Pak, reader: &R, outdir: &Path, options: &'a UnpackOptions<'a>, records_iter: impl Iterator<Item=&'a Record>) -> Result<()>
+where R: ReadAt + Sync + ?Sized {
     let dirnames = if options.dirname_from_compression {
-        let mut zlib_outdir = outdir.to_path_buf();
-        let mut none_outdir = outdir.to_path_buf();
-
-        zlib_outdir.push("zlib");
-        none_outdir.push("none");
-
-        Some((zlib_outdir, none_outdir))
+        let mut dirnames = HashMap::new();
+        for record in pak.index().records() {
+            let method = record.compression_method();
+            dirnames.entry(method).or_insert_with(|| {
+                let dirname = match method {
+                    COMPR_NONE => "none".to_string(),
+                    // the bias methods of old paks are zlib
+                    COMPR_BIAS_MEMORY | COMPR_BIAS_SPEED if pak.version() < PAK_COMPRESSION_METHOD_NAMES_VERSION =>
+                        "zlib".to_string(),
+                    _ => pak.compression_method_name(method).to_lowercase(),
+                };
+                outdir.join(dirname)
+            });
+        }
+        Some(dirnames)
     } else {
         None
     };
 
-    let pak_path = in_file.path()?;
+    let encryption_key = options.encryption_key.clone()
+        .or_else(|| pak.encryption_key().map(<[u8]>::to_vec));
 
     let thread_result = thread::scope::<_, Result<()>>(|scope| {
         let (work_sender, work_receiver) = unbounded();
@@ -70,11 +73,10 @@ fn unpack_iter<'a>(pak: &Pak, in_file: &mut File, outdir: &Path, options: &'a Un
         for _ in 0..options.thread_count.get() {
             let work_receiver = work_receiver.clone();
             let result_sender = result_sender.clone();
-            let mut in_file = File::open(&pak_path)?;
+            let encryption_key = encryption_key.clone();
 
             scope.spawn(move |_| {
-                let in_file = &mut in_file;
-                if let Err(error) = worker_proc(in_file, version, variant, options.encryption_key.clone(), work_receiver, result_sender) {
+                if let Err(error) = worker_proc(pak, reader, encryption_key, work_receiver, result_sender) {
                     if !error.error_type().is_channel_disconnected() {
                         eprintln!("error in worker thread: {}", error);
                     }
@@ -85,10 +87,9 @@ fn unpack_iter<'a>(pak: &Pak, in_file: &mut File, outdir: &Path, options: &'a Un
         drop(work_receiver);
         drop(result_sender);
 
-        if let Some((zlib_outdir, none_outdir)) = &dirnames {
+        if let Some(dirnames) = &dirnames {
             for record in records_iter {
-                let method = record.compression_method();
-                let outdir = if method == COMPR_NONE { &none_outdir } else { &zlib_outdir };
+                let outdir = &dirnames[&record.compression_method()];
 
                 match work_sender.send(Work { record, outdir }) {
                     Ok(()) => {}
@@ -143,7 +144,8 @@ fn unpack_iter<'a>(pak: &Pak, in_file: &mut File, outdir: &Path, options: &'a Un
     }
 }
 
-pub fn unpack<'a>(pak: &Pak, in_file: &mut File, outdir: impl AsRef<Path>, options: UnpackOptions<'a>) -> Result<()> {
+pub fn unpack<'a, R>(pak: &Pak, reader: &R, outdir: impl AsRef<Path>, options: UnpackOptions<'a>) -> Result<()>
+where R: ReadAt + Sync + ?Sized {
     let outdir = outdir.as_ref();
 
     if let Some(paths) = options.paths {
@@ -151,23 +153,22 @@ pub fn unpack<'a>(pak: &Pak, in_file: &mut File, outdir: impl AsRef<Path>, optio
         let records = pak.index().records().iter()
             .filter(|record| filter.visit(record.filename()));
 
-        unpack_iter(pak, in_file, outdir, &options, records)?;
+        unpack_iter(pak, reader, outdir, &options, records)?;
         filter.assert_all_visited()?;
     } else {
-        unpack_iter(pak, in_file, outdir, &options, pak.index().records().iter())?;
+        unpack_iter(pak, reader, outdir, &options, pak.index().records().iter())?;
     }
     Ok(())
 }
 
-pub fn unpack_record(record: &Record, version: u32, variant: Variant, in_file: &mut File, outdir: impl AsRef<Path>, encryption_key: Option<Vec<u8>>) -> Result<PathBuf> {
-    let header_size = pak::Pak::header_size(version, variant, record);
-    
+pub fn unpack_record<R>(pak: &Pak, record: &Record, reader: &R, outdir: impl AsRef<Path>, encryption_key: Option<Vec<u8>>) -> Result<PathBuf>
+where R: ReadAt + ?Sized {
     let mut path = outdir.as_ref().to_path_buf();
     for component in parse_pak_path(record.filename()) {
         path.push(component);
     }
     
-    let mut out_file = match OpenOptions::new()
+    let out_file = match OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
@@ -187,65 +188,12 @@ pub fn unpack_record(record: &Record, version: u32, variant: Variant, in_file: &
         }
     };
 
-    let start_offset = record.offset() + header_size;
-    in_file.seek(SeekFrom::Start(start_offset))?;
-
-    // Encrypted files need to be read in 16 byte blocks
-    let buffer_length = if record.encrypted() {
-        align(record.size(), BLOCK_SIZE as u64)
-    } else {
-        record.size()
-    } as usize;
-
-    let mut in_buffer = vec![0u8; buffer_length];
-    in_file.read_exact(&mut in_buffer)?;
-    
-    decrypt_entry(&mut in_buffer, record, encryption_key, record.size() as usize)?;
     debug!("unpacking {:?}", record);
 
-    match record.compression_method() {
-        pak::COMPR_NONE => {
-            out_file.write_all(&in_buffer)?;
-            out_file.flush()?;
-        }
-        pak::COMPR_ZLIB => {
-            if let Some(blocks) = record.compression_blocks() {
-                let mut out_file = BufWriter::new(out_file);
-
-                let mut out_buffer = Vec::with_capacity(record.compression_block_size() as usize);
-
-                for block in blocks {
-                    let mut block_start = (block.start_offset - header_size) as usize;
-                    let mut block_end = (block.end_offset - header_size) as usize;
-
-                    if version < PAK_RELATIVE_COMPRESSION_OFFSET_VERSION {
-                        block_start -= (start_offset - header_size) as usize;
-                        block_end -= (start_offset - header_size) as usize;
-                    }
-
-                    let mut zlib = ZlibDecoder::new(&in_buffer[block_start..block_end]);
-                    out_buffer.clear();
-                    zlib.read_to_end(&mut out_buffer)?;
-                    out_file.write_all(&out_buffer)?;
-                }
-                out_file.flush()?;
-            } else {
-                // version 2 has compression support, but not compression blocks
-                let mut out_buffer = Vec::new();
-
-                let mut zlib = ZlibDecoder::new(&in_buffer[..]);
-                zlib.read_to_end(&mut out_buffer)?;
-                out_file.write_all(&out_buffer)?;
-                out_file.flush()?;
-            }
-        }
-        _ => {
-            return Err(Error::new(format!(
-                    "unsupported compression method: {}",
-                    compression_method_name(record.compression_method())))
-                .with_path(record.filename()));
-        }
-    }
+    let mut entry = EntryReader::with_key(pak, ReadAtCursor::new(reader), record, encryption_key.as_deref())?;
+    let mut out_file = BufWriter::with_capacity(BUFFER_SIZE, out_file);
+    std::io::copy(&mut entry, &mut out_file)?;
+    out_file.flush()?;
 
     Ok(path)
 }
@@ -256,9 +204,10 @@ struct Work<'a> {
     outdir: &'a Path,
 }
 
-fn worker_proc(in_file: &mut File, version: u32, variant: Variant, encryption_key: Option<Vec<u8>>, work_channel: Receiver<Work>, result_channel: Sender<Result<PathBuf>>) -> Result<()> {
+fn worker_proc<R>(pak: &Pak, reader: &R, encryption_key: Option<Vec<u8>>, work_channel: Receiver<Work>, result_channel: Sender<Result<PathBuf>>) -> Result<()>
+where R: ReadAt + ?Sized {
     while let Ok(Work { record, outdir }) = work_channel.recv() {
-        let result = unpack_record(record, version, variant, in_file, outdir, encryption_key.clone())
+        let result = unpack_record(pak, record, reader, outdir, encryption_key.clone())
             .map_err(|error| error
                 .with_path_if_none(record.filename()));
 
@@ -267,18 +216,3 @@ fn worker_proc(in_file: &mut File, version: u32, variant: Variant, encryption_ke
 
     Ok(())
 }
-
-fn decrypt_entry(buffer: &mut Vec<u8>, record: &Record, encryption_key: Option<Vec<u8>>, size: usize) -> Result<()> {
-    if record.encrypted() {
-        if let Some(key) = encryption_key {
-            decrypt(buffer, &key);
-            // Trim padded bytes from undersized encryption blocks
-            buffer.truncate(size);
-        } else {
-            return Err(Error::new(
-                "File is encrypted, but no encryption key was provided".to_string(),
-            ).with_path(record.filename()));
-        }
-    }
-    Ok(())
-}
\ No newline at end of file
@@ -193,6 +193,21 @@ pub fn align(val: u64, alignment: u64) -> u64 {
     (val + alignment - 1) & !(alignment - 1)
 }
 
+// CRC-32 of a string like Unreal Engine's FCrc::StrCrc32(), which feeds every
+// UTF-16 code unit as 32bit little endian value.
+pub fn str_crc32(value: &str) -> u32 {
+    let mut crc = !0u32;
+    for ch in value.encode_utf16() {
+        for byte in (ch as u32).to_le_bytes().iter() {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+            }
+        }
+    }
+    !crc
+}
+
 pub const COMPR_LEVEL_FAST:    NonZeroU32 = unsafe { NonZeroU32::new_unchecked(1) };
 pub const COMPR_LEVEL_DEFAULT: NonZeroU32 = unsafe { NonZeroU32::new_unchecked(6) };
 pub const COMPR_LEVEL_BEST:    NonZeroU32 = unsafe { NonZeroU32::new_unchecked(9) };
@@ -232,3 +247,32 @@ pub fn sha1_digest<R: Read>(mut reader: R) -> Result<[u8; 20]> {
 
     Ok(hasher.finish())
 }
+
+// Parses a GUID like Unreal Engine's FGuid as 32 hex digits (dashes are
+// ignored). FGuid consists of four 32bit integers that are each serialized in
+// little endian, which is how the GUID is stored in the u128 as well.
+pub fn parse_guid(value: &str) -> Result<u128> {
+    let digits: String = value.chars().filter(|&ch| ch != '-').collect();
+    if digits.len() != 32 || !digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return Err(Error::new(format!("illegal GUID: {:?}", value)));
+    }
+
+    let mut bytes = [0u8; 16];
+    for index in 0..4 {
+        let part = u32::from_str_radix(&digits[index * 8..(index + 1) * 8], 16)?;
+        bytes[index * 4..(index + 1) * 4].copy_from_slice(&part.to_le_bytes());
+    }
+
+    Ok(u128::from_le_bytes(bytes))
+}
+
+// Formats a GUID like FGuid::ToString() does, as 32 upper case hex digits.
+pub fn format_guid(guid: u128) -> String {
+    let bytes = guid.to_le_bytes();
+    let mut buf = String::with_capacity(32);
+    for part in bytes.chunks(4) {
+        let part = u32::from_le_bytes([part[0], part[1], part[2], part[3]]);
+        buf.push_str(&format!("{:08X}", part));
+    }
+    buf
+}
@@ -0,0 +1,204 @@
+mod util;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+
+use u4pak::codec::{Codec, CodecRegistry, ZlibCodec};
+use u4pak::check::CheckOptions;
+use u4pak::pack::{FileOptions, PackOptions, PackPath};
+use u4pak::pak::{COMPR_CUSTOM, COMPR_NONE, COMPR_ZLIB, Options};
+use u4pak::unpack::UnpackOptions;
+use u4pak::{Pak, PakWriter, Result};
+use util::{remove_dir_all_if_exists, remove_file_if_exists};
+
+const COMPR_XOR: u32 = COMPR_CUSTOM + 1;
+
+// Not a compression at all, but good enough to see that the codec is used.
+struct XorCodec;
+
+impl Codec for XorCodec {
+    fn name(&self) -> &str {
+        "Xor"
+    }
+
+    fn method(&self) -> u32 {
+        COMPR_XOR
+    }
+
+    fn compress_block(&self, input: &[u8], _level: u32, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        // dropping trailing zeros so the "compressed" data is smaller
+        let len = input.iter().rposition(|&byte| byte != 0).map(|index| index + 1).unwrap_or(0);
+        output.extend(input[..len].iter().map(|byte| byte ^ 0x5a));
+        Ok(())
+    }
+
+    fn decompress_block(&self, input: &[u8], uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        output.clear();
+        output.extend(input.iter().map(|byte| byte ^ 0x5a));
+        output.resize(uncompressed_size, 0);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_codec() -> Result<()> {
+    let pak_path = "./custom_codec-it.pak";
+    let out_dir = "./custom_codec-it";
+    let source_dir = "./custom_codec-it-source";
+    remove_dir_all_if_exists(out_dir)?;
+    remove_dir_all_if_exists(source_dir)?;
+
+    std::fs::create_dir_all(source_dir)?;
+    let mut data = b"some data that is padded with a lot of zeros".to_vec();
+    data.resize(4096, 0);
+    std::fs::write(format!("{}/padded.bin", source_dir), &data)?;
+
+    let mut codecs = CodecRegistry::default();
+    codecs.register(XorCodec);
+    let codecs = Arc::new(codecs);
+
+    let mut source = PackPath::new(source_dir.to_string());
+    source.rename = Some("/".to_string());
+
+    u4pak::pack::pack(pak_path, &[source], PackOptions {
+        version: 11,
+        compression_method: COMPR_XOR,
+        codecs: codecs.clone(),
+        ..PackOptions::default()
+    })?;
+
+    let pak = Pak::from_path(pak_path, Options {
+        codecs,
+        ..Options::default()
+    })?;
+    assert_eq!(pak.compression_methods(), &["Xor".to_string()]);
+    for record in pak.index().records() {
+        assert_ne!(record.compression_method(), COMPR_NONE);
+        assert_eq!(pak.codec(record.compression_method()).map(|codec| codec.method()), Some(COMPR_XOR));
+    }
+
+    let file = File::open(pak_path)?;
+    u4pak::unpack::unpack(&pak, &file, out_dir, UnpackOptions::default())?;
+    util::validate(source_dir, out_dir)?;
+
+    // without the codec the pak can still be read, but not unpacked
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let record = &pak.index().records()[0];
+    assert!(pak.codec(record.compression_method()).is_none());
+    assert!(u4pak::unpack::unpack(&pak, &file, out_dir, UnpackOptions::default()).is_err());
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_dir_all_if_exists(source_dir)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_custom_codec_unsupported_version() {
+    let pak_path = "./custom_codec_v7-it.pak";
+
+    let mut codecs = CodecRegistry::default();
+    codecs.register(XorCodec);
+
+    let mut source = PackPath::new("./pak-examples/original-files".to_string());
+    source.rename = Some("/".to_string());
+
+    let result = u4pak::pack::pack(pak_path, &[source], PackOptions {
+        version: 7,
+        compression_method: COMPR_XOR,
+        codecs: Arc::new(codecs),
+        ..PackOptions::default()
+    });
+    let _ = remove_file_if_exists(pak_path);
+
+    assert!(result.is_err());
+}
+
+// Writes broken zlib blocks in place of the default zlib codec.
+enum BrokenZlib {
+    TrailingGarbage,
+    ShortBlocks,
+}
+
+impl Codec for BrokenZlib {
+    fn name(&self) -> &str {
+        ZlibCodec.name()
+    }
+
+    fn method(&self) -> u32 {
+        COMPR_ZLIB
+    }
+
+    fn compress_block(&self, input: &[u8], level: u32, output: &mut Vec<u8>) -> Result<()> {
+        match self {
+            BrokenZlib::TrailingGarbage => {
+                ZlibCodec.compress_block(input, level, output)?;
+                output.extend_from_slice(b"garbage");
+            }
+            BrokenZlib::ShortBlocks => {
+                ZlibCodec.compress_block(&input[..input.len() / 2], level, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decompress_block(&self, input: &[u8], uncompressed_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        ZlibCodec.decompress_block(input, uncompressed_size, output)
+    }
+}
+
+fn check_deep_broken_zlib(name: &str, codec: BrokenZlib, version: u32, encrypted: bool) -> Result<String> {
+    let pak_path = format!("./broken_zlib_{}-it.pak", name);
+    let encryption_key = if encrypted { Some(vec![0x42; 32]) } else { None };
+
+    let mut codecs = CodecRegistry::default();
+    codecs.register(codec);
+
+    let data = b"compressible data ".repeat(10_000);
+    let mut writer = PakWriter::new(BufWriter::new(File::create(&pak_path)?), PackOptions {
+        version,
+        compression_method: COMPR_ZLIB,
+        codecs: Arc::new(codecs),
+        encryption_key: encryption_key.clone(),
+        ..PackOptions::default()
+    })?;
+    writer.add_file("data.bin", &data[..], &FileOptions::default())?;
+    writer.finish()?;
+
+    // read with the default zlib codec
+    let pak = Pak::from_path(&pak_path, Options {
+        encryption_key,
+        ..Options::default()
+    })?;
+    let result = u4pak::check::check(&pak, &File::open(&pak_path)?, CheckOptions {
+        abort_on_error: true,
+        deep: true,
+        ..CheckOptions::default()
+    });
+    remove_file_if_exists(&pak_path)?;
+
+    match result {
+        Ok(_) => panic!("{}: check passed", name),
+        Err(error) => Ok(error.to_string()),
+    }
+}
+
+#[test]
+fn test_check_deep_trailing_garbage() -> Result<()> {
+    for (name, version, encrypted) in [("v4", 4, false), ("v11", 11, false), ("v11_encrypted", 11, true)] {
+        let message = check_deep_broken_zlib(&format!("garbage_{}", name), BrokenZlib::TrailingGarbage, version, encrypted)?;
+        assert!(message.contains("7 bytes of trailing garbage"), "{}: {}", name, message);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_deep_short_block() -> Result<()> {
+    for (name, version, encrypted) in [("v4", 4, false), ("v11", 11, false), ("v11_encrypted", 11, true)] {
+        let message = check_deep_broken_zlib(&format!("short_{}", name), BrokenZlib::ShortBlocks, version, encrypted)?;
+        assert!(message.contains("compression block with index 0 decompresses to 32768 bytes"), "{}: {}", name, message);
+    }
+    Ok(())
+}
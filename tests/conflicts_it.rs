@@ -0,0 +1,137 @@
+mod util;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use serde_json::Value;
+use u4pak::conflicts::{conflicts, CopyState};
+use u4pak::pack::{FileOptions, PackOptions};
+use u4pak::pak::Options;
+use u4pak::{Pak, PakWriter, Result};
+use util::remove_dir_all_if_exists;
+
+fn write_pak(path: impl AsRef<Path>, version: u32, mount_point: &str, files: &[(&str, &str)]) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer = PakWriter::new(BufWriter::new(File::create(path)?), PackOptions {
+        version,
+        mount_point: Some(mount_point),
+        ..PackOptions::default()
+    })?.with_path(path);
+
+    for (filename, data) in files {
+        writer.add_file(filename, data.as_bytes(), &FileOptions::default())?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+// returns the paths of the paks in load order
+fn write_paks(dir: &str) -> Result<Vec<String>> {
+    remove_dir_all_if_exists(dir)?;
+    std::fs::create_dir_all(dir)?;
+
+    let paths: Vec<String> = ["Game.pak", "ModA.pak", "ModB.pak"].iter()
+        .map(|name| format!("{}/{}", dir, name))
+        .collect();
+
+    write_pak(&paths[0], 11, "../../../Game/", &[
+        ("Config/A.ini", "base"),
+        ("Config/B.ini", "base"),
+        ("Content/C.uasset", "base"),
+    ])?;
+    write_pak(&paths[1], 11, "../../../", &[
+        ("Game/Config/A.ini", "base"),
+        ("Game/Config/B.ini", "mod a"),
+        ("Game/Content/ModA.uasset", "mod a"),
+    ])?;
+    // SHA-1 from the index instead of the inline header
+    write_pak(&paths[2], 3, "../../../Game/", &[
+        ("Config/B.ini", "mod b"),
+    ])?;
+
+    Ok(paths)
+}
+
+#[test]
+fn test_conflicts() -> Result<()> {
+    let dir = "./conflicts-it";
+    let paths = write_paks(dir)?;
+
+    let paks = paths.iter()
+        .map(|path| Pak::from_path(path, Options::default()))
+        .collect::<Result<Vec<_>>>()?;
+    let files = paths.iter()
+        .map(File::open)
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    // version 11 indices have no SHA-1, so it is read from the inline headers
+    assert!(paks[0].index().records().iter().all(|record| record.sha1().is_none()));
+
+    let conflicts = conflicts(&paks, &files)?;
+    assert_eq!(conflicts.len(), 2);
+
+    let a = &conflicts[0];
+    assert_eq!(a.path(), "Game/Config/A.ini");
+    assert_eq!(a.copies().iter().map(|copy| copy.pak_index()).collect::<Vec<_>>(), &[0, 1]);
+    assert_eq!(a.winner().pak_index(), 1);
+    assert!(a.winner().sha1().is_some());
+    assert_eq!(a.state(0), CopyState::Identical);
+    assert_eq!(a.state(1), CopyState::Winner);
+    assert_eq!(a.identical(), Some(true));
+
+    let b = &conflicts[1];
+    assert_eq!(b.path(), "Game/Config/B.ini");
+    assert_eq!(b.copies().iter().map(|copy| copy.pak_index()).collect::<Vec<_>>(), &[0, 1, 2]);
+    assert_eq!(b.winner().pak_index(), 2);
+    assert_eq!(b.state(0), CopyState::Different);
+    assert_eq!(b.state(1), CopyState::Different);
+    assert_eq!(b.identical(), Some(false));
+
+    assert!(u4pak::conflicts::conflicts(&paks, &files[..2]).is_err());
+
+    remove_dir_all_if_exists(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_conflicts_command() -> Result<()> {
+    let dir = "./conflicts_command-it";
+    let paths = write_paks(dir)?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_u4pak"))
+        .arg("conflicts")
+        .arg("--json")
+        .args(&paths)
+        .output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let report = report.as_array().unwrap();
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[1]["path"], "Game/Config/B.ini");
+    assert_eq!(report[1]["winner"], paths[2].as_str());
+    assert_eq!(report[1]["identical"], false);
+    let statuses: Vec<_> = report[1]["copies"].as_array().unwrap().iter()
+        .map(|copy| copy["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, &["differs", "differs", "wins"]);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_u4pak"))
+        .args(["conflicts", "--no-header", "--only-different"])
+        .args(&paths)
+        .output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<Vec<&str>> = stdout.lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(rows.len(), 3, "unexpected output: {}", stdout);
+    assert!(rows.iter().all(|row| row[3] == "Game/Config/B.ini"));
+    assert_eq!(rows[2][0], "wins");
+
+    remove_dir_all_if_exists(dir)?;
+    Ok(())
+}
@@ -0,0 +1,154 @@
+mod util;
+
+use std::fs::File;
+use std::sync::Arc;
+
+use u4pak::dictionary::Dictionary;
+use u4pak::pak::{COMPR_NONE, COMPR_ZLIB, Options};
+use u4pak::unpack::UnpackOptions;
+use u4pak::{Pak, Result};
+use util::{remove_dir_all_if_exists, remove_file_if_exists};
+
+// Packs a pak and then sets the size of the full directory index to -1, which
+// marks it as missing, so only the path hash index is left to read the records from.
+// Returns the sorted paths of the records.
+fn pack_hash_index_only(pak_path: &str, version: u32, compression_method: u32) -> Result<Vec<String>> {
+    util::pack(pak_path, "./pak-examples/original-files", version, compression_method)?;
+
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let mut paths: Vec<String> = pak.index().records().iter().map(|record| record.filename().to_string()).collect();
+    paths.sort();
+
+    let mut data = std::fs::read(pak_path)?;
+    let index_offset = pak.index_offset() as usize;
+    let mount_point_size = i32::from_le_bytes([
+        data[index_offset], data[index_offset + 1], data[index_offset + 2], data[index_offset + 3]
+    ]) as usize;
+    // mount point, entry count, path hash seed, path hash index flag, offset, size and hash,
+    // full directory index flag and offset
+    let offset = index_offset + 4 + mount_point_size + 4 + 8 + 4 + 8 + 8 + 20 + 4 + 8;
+    data[offset..offset + 8].copy_from_slice(&(-1i64).to_le_bytes());
+    std::fs::write(pak_path, &data)?;
+
+    Ok(paths)
+}
+
+fn read_with_dictionary(pak_path: &str, dictionary: Dictionary) -> Result<Pak> {
+    Pak::from_path(pak_path, Options {
+        dictionary: Arc::new(dictionary),
+        ..Options::default()
+    })
+}
+
+fn sorted_filenames(pak: &Pak) -> Vec<String> {
+    let mut filenames: Vec<String> = pak.index().records().iter().map(|record| record.filename().to_string()).collect();
+    filenames.sort();
+    filenames
+}
+
+fn test_dictionary(version: u32) -> Result<()> {
+    let pak_path = format!("./dictionary_v{}-it.pak", version);
+    let out_dir = format!("./dictionary_v{}-it", version);
+    remove_dir_all_if_exists(&out_dir)?;
+
+    let paths = pack_hash_index_only(&pak_path, version, COMPR_ZLIB)?;
+
+    let pak = Pak::from_path(&pak_path, Options::default())?;
+    assert_eq!(pak.index().records().len(), paths.len());
+    assert!(pak.index().records().iter().all(|record| !paths.contains(&record.filename().to_string())));
+
+    let pak = read_with_dictionary(&pak_path, Dictionary::from_text(&paths.join("\n")))?;
+    assert_eq!(sorted_filenames(&pak), paths);
+
+    let file = File::open(&pak_path)?;
+    u4pak::unpack::unpack(&pak, &file, &out_dir, UnpackOptions::default())?;
+    util::validate("./pak-examples/original-files", &out_dir)?;
+
+    remove_dir_all_if_exists(&out_dir)?;
+    remove_file_if_exists(&pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_dictionary_v10() -> Result<()> {
+    test_dictionary(10)
+}
+
+#[test]
+fn test_dictionary_v11() -> Result<()> {
+    test_dictionary(11)
+}
+
+#[test]
+fn test_dictionary_partial() -> Result<()> {
+    let pak_path = "./dictionary_partial-it.pak";
+
+    let paths = pack_hash_index_only(pak_path, 11, COMPR_NONE)?;
+
+    // different case and separators, comments, and paths that aren't in the pak
+    let mut text = String::from("# partial dictionary\n\nNot/In/The/Pak.txt\n");
+    text.push_str(&format!("/{}\n", paths[0].to_uppercase().replace('/', "\\")));
+
+    let pak = read_with_dictionary(pak_path, Dictionary::from_text(&text))?;
+    let filenames = sorted_filenames(&pak);
+    assert_eq!(filenames.len(), paths.len());
+    assert!(filenames.contains(&paths[0].to_uppercase()));
+    for path in &paths[1..] {
+        assert!(!filenames.contains(path));
+    }
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_dictionary_export() -> Result<()> {
+    let source_path = "./dictionary_export_source-it.pak";
+    let target_path = "./dictionary_export_target-it.pak";
+    let dictionary_path = "./dictionary_export-it.txt";
+
+    util::pack(source_path, "./pak-examples/original-files", 11, COMPR_NONE)?;
+    let paths = pack_hash_index_only(target_path, 11, COMPR_ZLIB)?;
+
+    let mut dictionary = Dictionary::new();
+    dictionary.insert_pak(&Pak::from_path(source_path, Options::default())?)?;
+    for path in &paths {
+        assert!(dictionary.paths().any(|other| other == path));
+        assert!(dictionary.paths().any(|other| other == path.to_lowercase()));
+    }
+
+    // paks without full directory index can't be exported
+    let target = Pak::from_path(target_path, Options::default())?;
+    assert!(Dictionary::new().insert_pak(&target).is_err());
+
+    let mut file = File::create(dictionary_path)?;
+    dictionary.write_to(&mut file)?;
+    drop(file);
+    let dictionary = Dictionary::from_path(dictionary_path)?;
+
+    let coverage = dictionary.coverage(&target, &mut File::open(target_path)?)?;
+    assert_eq!(coverage.path_hash_count, paths.len());
+    assert_eq!(coverage.resolved_count, paths.len());
+
+    let coverage = Dictionary::from_text(&paths[1..].join("\n")).coverage(&target, &mut File::open(target_path)?)?;
+    assert_eq!(coverage.resolved_count, paths.len() - 1);
+
+    let pak = read_with_dictionary(target_path, dictionary)?;
+    assert_eq!(sorted_filenames(&pak), paths);
+
+    remove_file_if_exists(dictionary_path)?;
+    remove_file_if_exists(target_path)?;
+    remove_file_if_exists(source_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_path_hash_index_truncated() {
+    // claims u32::MAX entries, but only has one
+    let mut data = u32::MAX.to_le_bytes().to_vec();
+    data.extend_from_slice(&[0; 12]);
+    assert!(u4pak::index::read_path_hash_index(&data).is_err());
+
+    data[..4].copy_from_slice(&1u32.to_le_bytes());
+    assert_eq!(u4pak::index::read_path_hash_index(&data).unwrap(), vec![(0, 0)]);
+}
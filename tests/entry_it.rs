@@ -0,0 +1,176 @@
+mod util;
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use u4pak::pack::{PackOptions, PackPath};
+use u4pak::pak::{COMPR_NONE, COMPR_ZLIB, Options};
+use u4pak::{Pak, Result};
+use util::remove_file_if_exists;
+
+const ENCRYPTION_KEY: &str = "aWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWk=";
+const SOURCE_DIR: &str = "./pak-examples/original-files";
+
+// small compression blocks so that files span several of them
+fn pack(pak_path: &str, version: u32, compression_method: u32, encryption_key: Option<&str>) -> Result<()> {
+    let mut source = PackPath::new(SOURCE_DIR.to_string());
+    source.rename = Some("/".to_string());
+
+    u4pak::pack::pack(pak_path, &[source], PackOptions {
+        version,
+        compression_method,
+        compression_block_size: NonZeroU32::new(4096).unwrap(),
+        encryption_key: encryption_key.map(|key| base64::decode(key).unwrap()),
+        ..PackOptions::default()
+    })?;
+
+    Ok(())
+}
+
+fn validate_entries(pak_path: &str, encryption_key: Option<&str>) -> Result<()> {
+    let pak = Pak::from_path(pak_path, Options {
+        encryption_key: encryption_key.map(|key| base64::decode(key).unwrap()),
+        ..Options::default()
+    })?;
+    let mut file = File::open(pak_path)?;
+
+    for record in pak.index().records() {
+        let expected = std::fs::read(Path::new(SOURCE_DIR).join(record.filename()))?;
+
+        let mut entry = pak.open_entry(&mut file, record)?;
+        assert_eq!(entry.len(), expected.len() as u64);
+
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        assert!(data == expected, "{}: data differs", record.filename());
+
+        // random access across compression and AES block boundaries
+        for &(offset, size) in &[(0u64, 1usize), (15, 2), (4095, 3), (5000, 10000), (12345, 17)] {
+            if offset >= expected.len() as u64 {
+                continue;
+            }
+            let end = (offset as usize + size).min(expected.len());
+            let mut buffer = vec![0u8; end - offset as usize];
+            entry.seek(SeekFrom::Start(offset))?;
+            entry.read_exact(&mut buffer)?;
+            assert!(buffer == expected[offset as usize..end], "{}: data at {} differs", record.filename(), offset);
+        }
+
+        if !expected.is_empty() {
+            let position = entry.seek(SeekFrom::End(-1))?;
+            assert_eq!(position, expected.len() as u64 - 1);
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer)?;
+            assert_eq!(buffer, &expected[expected.len() - 1..]);
+        }
+    }
+
+    // seeking before the start is an error
+    if let Some(record) = pak.index().records().first() {
+        let mut entry = pak.open_entry(&mut file, record)?;
+        assert!(entry.seek(SeekFrom::Current(-1)).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_entry_v3() -> Result<()> {
+    let pak_path = "./entry_v3-it.pak";
+    pack(pak_path, 3, COMPR_NONE, None)?;
+    validate_entries(pak_path, None)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_entry_v3_compressed() -> Result<()> {
+    let pak_path = "./entry_v3_compressed-it.pak";
+    pack(pak_path, 3, COMPR_ZLIB, None)?;
+    validate_entries(pak_path, None)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_entry_v11_compressed() -> Result<()> {
+    let pak_path = "./entry_v11_compressed-it.pak";
+    pack(pak_path, 11, COMPR_ZLIB, None)?;
+    validate_entries(pak_path, None)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_entry_v11_encrypted() -> Result<()> {
+    let pak_path = "./entry_v11_encrypted-it.pak";
+    pack(pak_path, 11, COMPR_NONE, Some(ENCRYPTION_KEY))?;
+    validate_entries(pak_path, Some(ENCRYPTION_KEY))?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_entry_v11_compressed_encrypted() -> Result<()> {
+    let pak_path = "./entry_v11_compressed_encrypted-it.pak";
+    pack(pak_path, 11, COMPR_ZLIB, Some(ENCRYPTION_KEY))?;
+    validate_entries(pak_path, Some(ENCRYPTION_KEY))?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_entry_missing_encryption_key() -> Result<()> {
+    let pak_path = "./entry_missing_encryption_key-it.pak";
+    pack(pak_path, 3, COMPR_ZLIB, Some(ENCRYPTION_KEY))?;
+
+    // version 3 has no encrypted index, so the pak can be read without key
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let mut file = File::open(pak_path)?;
+    let record = &pak.index().records()[0];
+    assert!(pak.open_entry(&mut file, record).is_err());
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_entry_block_before_data() -> Result<()> {
+    let pak_path = "./entry_block_before_data-it.pak";
+    pack(pak_path, 3, COMPR_ZLIB, Some(ENCRYPTION_KEY))?;
+
+    // let the first compression block of a record start at 0, i.e. before its data
+    let key = base64::decode(ENCRYPTION_KEY).unwrap();
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let record = pak.index().records().iter()
+        .find(|record| record.compression_blocks().is_some())
+        .unwrap();
+    let block = &record.compression_blocks().as_ref().unwrap()[0];
+    let mut pattern = block.start_offset.to_le_bytes().to_vec();
+    pattern.extend_from_slice(&block.end_offset.to_le_bytes());
+
+    let mut data = std::fs::read(pak_path)?;
+    let index_offset = pak.index_offset() as usize;
+    let offset = index_offset + data[index_offset..].windows(pattern.len())
+        .position(|window| window == pattern)
+        .unwrap();
+    data[offset..offset + 8].fill(0);
+    std::fs::write(pak_path, &data)?;
+
+    let pak = Pak::from_path(pak_path, Options {
+        encryption_key: Some(key),
+        ..Options::default()
+    })?;
+    let record = pak.index().records().iter()
+        .find(|record| matches!(record.compression_blocks(), Some(blocks) if blocks[0].start_offset == 0))
+        .unwrap();
+    let mut file = File::open(pak_path)?;
+    let mut entry = pak.open_entry(&mut file, record)?;
+    let error = entry.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", error);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
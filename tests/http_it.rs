@@ -0,0 +1,216 @@
+mod util;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use u4pak::check::CheckOptions;
+use u4pak::http::{HttpOptions, HttpReader};
+use u4pak::pak::{COMPR_ZLIB, Options};
+use u4pak::unpack::UnpackOptions;
+use u4pak::{Pak, ReadAt, ReadAtCursor, Result};
+use util::{remove_dir_all_if_exists, remove_file_if_exists};
+
+const SOURCE_DIR: &str = "./pak-examples/original-files";
+
+// Minimal HTTP/1.1 server with keep-alive and support for single byte ranges.
+// Paths starting with /redirect/ are redirected to the rest of the path,
+// /norange/ ignores the Range header, /biglength/ claims a huge body, and
+// /bigrange/ sends more than the requested range.
+struct Server {
+    url: String,
+    served_size: Arc<AtomicU64>,
+}
+
+fn serve(data: Vec<u8>) -> Server {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let data = Arc::new(data);
+    let served_size = Arc::new(AtomicU64::new(0));
+
+    {
+        let served_size = served_size.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                let data = data.clone();
+                let served_size = served_size.clone();
+                std::thread::spawn(move || {
+                    let _ = handle_connection(stream, &data, &served_size);
+                });
+            }
+        });
+    }
+
+    Server { url, served_size }
+}
+
+fn handle_connection(stream: TcpStream, data: &[u8], served_size: &AtomicU64) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let path = line.split(' ').nth(1).unwrap_or("/").to_string();
+
+        let mut range = None;
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Range: bytes=") {
+                let mut parts = value.split('-');
+                let start: usize = parts.next().unwrap().parse().unwrap();
+                let end: usize = parts.next().unwrap().parse().unwrap();
+                range = Some((start, end + 1));
+            }
+        }
+
+        if let Some(location) = path.strip_prefix("/redirect") {
+            write!(writer, "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n", location)?;
+            continue;
+        }
+
+        match range {
+            Some((start, end)) if path.starts_with("/biglength/") && start < data.len() => {
+                let end = end.min(data.len());
+                write!(writer, "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                    start, end - 1, data.len(), u64::MAX / 2)?;
+                writer.write_all(&data[start..end])?;
+            }
+            Some((start, _)) if path.starts_with("/bigrange/") && start < data.len() => {
+                write!(writer, "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                    start, data.len() - 1, data.len(), data.len() - start)?;
+                writer.write_all(&data[start..])?;
+            }
+            Some((start, end)) if !path.starts_with("/norange/") && start < data.len() => {
+                let end = end.min(data.len());
+                write!(writer, "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                    start, end - 1, data.len(), end - start)?;
+                writer.write_all(&data[start..end])?;
+                served_size.fetch_add((end - start) as u64, Ordering::Relaxed);
+            }
+            _ => {
+                write!(writer, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", data.len())?;
+                writer.write_all(data)?;
+            }
+        }
+        writer.flush()?;
+    }
+}
+
+fn pack(pak_path: &str) -> Result<Vec<u8>> {
+    util::pack(pak_path, SOURCE_DIR, 11, COMPR_ZLIB)?;
+    let data = std::fs::read(pak_path)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(data)
+}
+
+fn small_blocks() -> HttpOptions {
+    HttpOptions {
+        block_size: NonZeroU64::new(4096).unwrap(),
+        cache_size: 4,
+        ..HttpOptions::default()
+    }
+}
+
+#[test]
+fn test_http_unpack() -> Result<()> {
+    let out_dir = "./http_unpack-it";
+    remove_dir_all_if_exists(out_dir)?;
+
+    let data = pack("./http_unpack-it.pak")?;
+    let server = serve(data.clone());
+
+    let reader = HttpReader::open(&format!("{}/test.pak", server.url), small_blocks())?;
+    assert_eq!(reader.size()?, data.len() as u64);
+
+    let pak = Pak::from_reader(&mut ReadAtCursor::new(&reader), Options::default())?;
+
+    u4pak::check::check(&pak, &reader, CheckOptions {
+        abort_on_error: true,
+        deep: true,
+        ..CheckOptions::default()
+    })?;
+
+    u4pak::unpack::unpack(&pak, &reader, out_dir, UnpackOptions::default())?;
+    util::validate(SOURCE_DIR, out_dir)?;
+
+    remove_dir_all_if_exists(out_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_http_partial() -> Result<()> {
+    let data = pack("./http_partial-it.pak")?;
+    let server = serve(data.clone());
+
+    let reader = HttpReader::open(&format!("{}/redirect/test.pak", server.url), small_blocks())?;
+    assert_eq!(reader.url(), format!("{}/test.pak", server.url));
+
+    let pak = Pak::from_reader(&mut ReadAtCursor::new(&reader), Options::default())?;
+    let record = pak.index().records().iter()
+        .min_by_key(|record| record.size())
+        .unwrap();
+
+    let expected = std::fs::read(format!("{}/{}", SOURCE_DIR, record.filename()))?;
+    let mut buffer = Vec::new();
+    pak.open_entry(ReadAtCursor::new(&reader), record)?.read_to_end(&mut buffer)?;
+    assert!(buffer == expected, "{}: data differs", record.filename());
+
+    // only the first block, the footer, the index, and the file were downloaded
+    let served_size = server.served_size.load(Ordering::Relaxed);
+    assert_eq!(served_size, reader.downloaded_size());
+    assert!(served_size < data.len() as u64 / 2,
+        "downloaded {} of {} bytes", served_size, data.len());
+
+    // cached blocks aren't requested again
+    let request_count = reader.request_count();
+    let mut footer = [0u8; 16];
+    reader.read_exact_at(&mut footer, data.len() as u64 - 16)?;
+    assert_eq!(&footer, &data[data.len() - 16..]);
+    assert_eq!(reader.request_count(), request_count);
+
+    Ok(())
+}
+
+#[test]
+fn test_http_errors() -> Result<()> {
+    let data = pack("./http_errors-it.pak")?;
+    let server = serve(data);
+
+    assert!(HttpReader::open(&format!("{}/norange/test.pak", server.url), small_blocks()).is_err());
+    assert!(HttpReader::open(&format!("{}/biglength/test.pak", server.url), small_blocks()).is_err());
+    assert!(HttpReader::open(&format!("{}/bigrange/test.pak", server.url), small_blocks()).is_err());
+    assert!(HttpReader::open("ftp://localhost/test.pak", small_blocks()).is_err());
+    assert!(HttpReader::open("http://localhost:port/test.pak", small_blocks()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_http_list_command() -> Result<()> {
+    let data = pack("./http_list_command-it.pak")?;
+    let server = serve(data);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_u4pak"))
+        .args(["list", "--only-names", &format!("{}/test.pak", server.url)])
+        .output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.lines().any(|line| line == "Small.txt"), "unexpected output: {}", stdout);
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+mod util;
+
+use std::fs::File;
+use std::sync::Arc;
+
+use u4pak::keyring::Keyring;
+use u4pak::pack::{PackOptions, PackPath};
+use u4pak::pak::{COMPR_ZLIB, Options};
+use u4pak::unpack::UnpackOptions;
+use u4pak::util::{format_guid, parse_guid};
+use u4pak::{Pak, Result};
+use util::{remove_dir_all_if_exists, remove_file_if_exists};
+
+const ENCRYPTION_KEY: &str = "aWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWk=";
+const OTHER_ENCRYPTION_KEY: &str = "b29vb29vb29vb29vb29vb29vb29vb29vb29vb29vb28=";
+const ENCRYPTION_KEY_GUID: &str = "1A2B3C4D-00000000-00000000-00000001";
+
+fn pack_with_guid(pak_path: &str) -> Result<()> {
+    let mut source = PackPath::new("./pak-examples/original-files".to_string());
+    source.rename = Some("/".to_string());
+
+    u4pak::pack::pack(pak_path, &[source], PackOptions {
+        version: 11,
+        compression_method: COMPR_ZLIB,
+        encryption_key: Some(base64::decode(ENCRYPTION_KEY).unwrap()),
+        encryption_key_guid: parse_guid(ENCRYPTION_KEY_GUID)?,
+        ..PackOptions::default()
+    })?;
+
+    Ok(())
+}
+
+fn unpack_with_keyring(pak_path: &str, keyring_path: &str, out_dir: &str) -> Result<()> {
+    let pak = Pak::from_path(pak_path, Options {
+        keyring: Arc::new(Keyring::from_path(keyring_path)?),
+        ..Options::default()
+    })?;
+    assert_eq!(format_guid(pak.encryption_guid()), "1A2B3C4D000000000000000000000001");
+    assert_eq!(pak.encryption_key(), Some(&base64::decode(ENCRYPTION_KEY).unwrap()[..]));
+
+    // a key given for unpacking wins over the one selected by the keyring
+    let file = File::open(pak_path)?;
+    assert!(u4pak::unpack::unpack(&pak, &file, out_dir, UnpackOptions {
+        encryption_key: Some(base64::decode(OTHER_ENCRYPTION_KEY).unwrap()),
+        ..UnpackOptions::default()
+    }).is_err());
+
+    remove_dir_all_if_exists(out_dir)?;
+    u4pak::unpack::unpack(&pak, &file, out_dir, UnpackOptions::default())?;
+    util::validate("./pak-examples/original-files", out_dir)
+}
+
+fn info(pak_path: &str, keyring_path: Option<&str>) -> Result<String> {
+    let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_u4pak"));
+    command.arg("info");
+    if let Some(keyring_path) = keyring_path {
+        command.args(["--keyring", keyring_path]);
+    }
+    let output = command.arg(pak_path).output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[test]
+fn test_keyring_json() -> Result<()> {
+    let pak_path = "./keyring_json-it.pak";
+    let keyring_path = "./keyring_json-it.json";
+    let out_dir = "./keyring_json-it";
+    remove_dir_all_if_exists(out_dir)?;
+
+    pack_with_guid(pak_path)?;
+    std::fs::write(keyring_path, format!(
+        "{{\n\t\"00000000000000000000000000000000\": \"{}\",\n\t\"{}\": \"{}\"\n}}\n",
+        OTHER_ENCRYPTION_KEY, ENCRYPTION_KEY_GUID, ENCRYPTION_KEY))?;
+
+    unpack_with_keyring(pak_path, keyring_path, out_dir)?;
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_file_if_exists(keyring_path)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_keyring_toml() -> Result<()> {
+    let pak_path = "./keyring_toml-it.pak";
+    let keyring_path = "./keyring_toml-it.toml";
+    let out_dir = "./keyring_toml-it";
+    remove_dir_all_if_exists(out_dir)?;
+
+    pack_with_guid(pak_path)?;
+    std::fs::write(keyring_path, format!(
+        "\"00000000000000000000000000000000\" = \"{}\"\n\"{}\" = \"{}\"\n",
+        OTHER_ENCRYPTION_KEY, ENCRYPTION_KEY_GUID, ENCRYPTION_KEY))?;
+
+    unpack_with_keyring(pak_path, keyring_path, out_dir)?;
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_file_if_exists(keyring_path)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_keyring_missing_guid() -> Result<()> {
+    let pak_path = "./keyring_missing_guid-it.pak";
+
+    pack_with_guid(pak_path)?;
+
+    let mut keyring = Keyring::new();
+    keyring.insert(0, base64::decode(ENCRYPTION_KEY).unwrap())?;
+
+    let error = Pak::from_path(pak_path, Options {
+        keyring: Arc::new(keyring),
+        ..Options::default()
+    }).unwrap_err();
+    assert!(error.to_string().contains("1A2B3C4D000000000000000000000001"), "{}", error);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_keyring_illegal_key() {
+    assert!(Keyring::from_json("{\"00000000000000000000000000000000\": \"c2hvcnQ=\"}").is_err());
+    assert!(Keyring::from_json("{\"not a guid\": \"aWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWk=\"}").is_err());
+}
+
+#[test]
+fn test_keyring_info() -> Result<()> {
+    let pak_path = "./keyring_info-it.pak";
+    let keyring_path = "./keyring_info-it.json";
+
+    pack_with_guid(pak_path)?;
+
+    // the index can't be decrypted, but the footer is still shown
+    let stdout = info(pak_path, None)?;
+    assert!(stdout.contains("Encrypted Index: yes\n"), "{}", stdout);
+    assert!(stdout.contains("Encryption Key GUID: 1A2B3C4D000000000000000000000001\n"), "{}", stdout);
+    assert!(stdout.contains("Keyring Key: not found\n"), "{}", stdout);
+    assert!(stdout.contains("Index can't be read: "), "{}", stdout);
+
+    std::fs::write(keyring_path, format!("{{\"{}\": \"{}\"}}", ENCRYPTION_KEY_GUID, ENCRYPTION_KEY))?;
+    let stdout = info(pak_path, Some(keyring_path))?;
+    assert!(stdout.contains("Keyring Key: found\n"), "{}", stdout);
+    assert!(stdout.contains("Encryption Key: available\n"), "{}", stdout);
+    assert!(!stdout.contains("Index can't be read"), "{}", stdout);
+
+    remove_file_if_exists(keyring_path)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
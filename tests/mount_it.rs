@@ -0,0 +1,140 @@
+#![cfg(target_os = "linux")]
+
+mod util;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use u4pak::mount::U4PakFS;
+use u4pak::pack::{FileOptions, PackOptions};
+use u4pak::pak::{COMPR_NONE, COMPR_ZLIB, Options};
+use u4pak::{Pak, PakSet, PakWriter, Result};
+use util::remove_dir_all_if_exists;
+
+fn write_pak(path: impl AsRef<Path>, files: &[(&str, &str)]) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer = PakWriter::new(BufWriter::new(File::create(path)?), PackOptions {
+        version: 11,
+        mount_point: Some("../../../Game/"),
+        ..PackOptions::default()
+    })?.with_path(path);
+
+    for (filename, data) in files {
+        writer.add_file(filename, data.as_bytes(), &FileOptions::default())?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+#[test]
+fn test_mount_set_pak_paths() -> Result<()> {
+    let dir = "./mount_set_pak_paths-it";
+    remove_dir_all_if_exists(dir)?;
+    std::fs::create_dir_all(dir)?;
+
+    let patch = format!("{}/pakchunk5-WindowsNoEditor_P.pak", dir);
+    let modded = format!("{}/MyMod.pak", dir);
+    let base = format!("{}/pakchunk5-WindowsNoEditor.pak", dir);
+    write_pak(&patch, &[
+        ("Config/A.ini", "patch"),
+        ("Config/B.ini", "patch"),
+    ])?;
+    write_pak(&modded, &[
+        ("Config/A.ini", "mod"),
+        ("Content/C.uasset", "mod"),
+    ])?;
+    write_pak(&base, &[
+        ("Config/B.ini", "base"),
+        ("Config/D.ini", "base"),
+    ])?;
+
+    // like `u4pak mount pakchunk5-WindowsNoEditor_P.pak MyMod.pak pakchunk5-WindowsNoEditor.pak`,
+    // the patch pak wins over later arguments
+    let mut set = PakSet::new();
+    for (index, path) in [&patch, &modded, &base].iter().enumerate() {
+        set.insert(path, Pak::from_path(path, Options::default())?, index as i32);
+    }
+
+    let fs = U4PakFS::from_set(&set)?;
+    for (path, pak_path) in [
+        ("Game/Config/A.ini",     &patch),
+        ("Game/Config/B.ini",     &patch),
+        ("Game/Content/C.uasset", &modded),
+        ("Game/Config/D.ini",     &base),
+    ] {
+        let inode = fs.lookup_path(path).unwrap_or_else(|| panic!("{}: not found", path));
+        assert_eq!(fs.pak_path(inode), Some(pak_path.as_str()), "{}", path);
+    }
+
+    let inode = fs.lookup_path("Game/Config").unwrap();
+    assert_eq!(fs.pak_path(inode), None);
+    assert_eq!(fs.lookup_path("Game/Config/Missing.ini"), None);
+    assert_eq!(fs.lookup_path("Game/Config/A.ini/A.ini"), None);
+
+    // a single pak has no source path
+    let fs = U4PakFS::from_file(&Pak::from_path(&modded, Options::default())?, File::open(&modded)?, None)?;
+    let inode = fs.lookup_path("Config/A.ini").unwrap();
+    assert_eq!(fs.pak_path(inode), None);
+
+    remove_dir_all_if_exists(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_mount_read_encrypted() -> Result<()> {
+    let pak_path = "./mount_read_encrypted-it.pak";
+    let key = vec![0x42; 32];
+
+    // sizes that aren't multiples of the AES block size
+    let data: Vec<u8> = (0..6151usize).map(|index| (index * 7 % 251) as u8 ^ (index / 100) as u8).collect();
+
+    let mut writer = PakWriter::new(BufWriter::new(File::create(pak_path)?), PackOptions {
+        version: 11,
+        encryption_key: Some(key.clone()),
+        ..PackOptions::default()
+    })?;
+    writer.add_file("plain.bin", &data[..], &FileOptions {
+        compression_method: COMPR_NONE,
+        ..FileOptions::default()
+    })?;
+    writer.add_file("zlib.bin", &data[..], &FileOptions {
+        compression_method: COMPR_ZLIB,
+        compression_block_size: NonZeroU32::new(2048),
+        ..FileOptions::default()
+    })?;
+    writer.finish()?;
+
+    let pak = Pak::from_path(pak_path, Options {
+        encryption_key: Some(key.clone()),
+        ..Options::default()
+    })?;
+    for record in pak.index().records() {
+        assert!(record.encrypted(), "{}", record.filename());
+    }
+    let record = pak.index().records().iter().find(|record| record.filename() == "zlib.bin").unwrap();
+    assert_eq!(record.compression_blocks().as_ref().map(Vec::len), Some(4));
+
+    let fs = U4PakFS::from_file(&pak, File::open(pak_path)?, Some(key))?;
+    for filename in ["plain.bin", "zlib.bin"] {
+        let inode = fs.lookup_path(filename).unwrap();
+        for offset in [0, 1, 15, 16, 17, 2047, 2048, 2049, 4095, 6134, 6150, 6151, 7000] {
+            for size in [1, 15, 16, 17, 33, 2048, 3000, 7000] {
+                let end = data.len().min(offset + size);
+                let expected = if offset < end { &data[offset..end] } else { &[][..] };
+                let actual = fs.read_file(inode, offset as u64, size as u32)
+                    .unwrap_or_else(|errno| panic!("{}: errno {} at {} + {}", filename, errno, offset, size));
+                assert!(actual == expected, "{}: data differs at {} + {}", filename, offset, size);
+            }
+        }
+    }
+
+    // without the key
+    let fs = U4PakFS::from_file(&pak, File::open(pak_path)?, None)?;
+    assert!(fs.read_file(fs.lookup_path("plain.bin").unwrap(), 0, 16).is_err());
+
+    util::remove_file_if_exists(pak_path)?;
+    Ok(())
+}
@@ -0,0 +1,491 @@
+mod util;
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use u4pak::{Pak, PakWriter, Result, Variant};
+use u4pak::pack::{FileOptions, PackOptions};
+use u4pak::unpack::UnpackOptions;
+use u4pak::pak::{COMPR_NONE, COMPR_ZLIB, COMPR_GZIP, COMPR_LZ4, Options, PAK_MAGIC, PAK_RELATIVE_COMPRESSION_OFFSET_VERSION};
+use util::{remove_dir_all_if_exists, remove_file_if_exists};
+
+const SOURCE_DIR: &str = "./pak-examples/original-files";
+const ENCRYPTION_KEY: &str = "aWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWlpaWk=";
+const OTHER_ENCRYPTION_KEY: &str = "b29vb29vb29vb29vb29vb29vb29vb29vb29vb29vb28=";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encryption {
+    None,
+    All,
+    IndexOnly,
+}
+
+// packs, checks, unpacks and compares with the source files
+fn test_pack(version: u32, compression_method: u32, encryption: Encryption) -> Result<Pak> {
+    let name = format!("pack_v{}_{}_{:?}-it", version, compression_method, encryption).to_lowercase();
+    let pak_path = format!("./{}.pak", name);
+    let out_dir = format!("./{}", name);
+    remove_dir_all_if_exists(&out_dir)?;
+
+    let encryption_key = if encryption == Encryption::None {
+        util::pack(&pak_path, SOURCE_DIR, version, compression_method)?;
+        assert_eq!(util::check(&pak_path)?, 0);
+        if compression_method != COMPR_NONE {
+            assert_eq!(util::check_deep(&pak_path)?, 0);
+        }
+        None
+    } else {
+        util::pack_encrypted(&pak_path, SOURCE_DIR, version, compression_method, ENCRYPTION_KEY, encryption == Encryption::IndexOnly)?;
+        assert_eq!(util::check_encrypted(&pak_path, ENCRYPTION_KEY)?, 0);
+        // the index or the data can't be read without the key
+        assert!(util::unpack(&pak_path, &out_dir, None).is_err());
+        Some(ENCRYPTION_KEY.to_string())
+    };
+
+    let pak = Pak::from_path(&pak_path, Options {
+        encryption_key: encryption_key.as_ref().map(|key| base64::decode(key).unwrap()),
+        ..Options::default()
+    })?;
+
+    util::unpack(&pak_path, &out_dir, encryption_key)?;
+    util::validate(SOURCE_DIR, &out_dir)?;
+
+    remove_dir_all_if_exists(&out_dir)?;
+    remove_file_if_exists(&pak_path)?;
+    Ok(pak)
+}
+
+#[test]
+fn test_pack_v4() -> Result<()> {
+    test_pack(4, COMPR_NONE, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v4_compressed() -> Result<()> {
+    test_pack(4, COMPR_ZLIB, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v5() -> Result<()> {
+    test_pack(5, COMPR_NONE, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v5_compressed() -> Result<()> {
+    test_pack(5, COMPR_ZLIB, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v7() -> Result<()> {
+    test_pack(7, COMPR_NONE, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v7_compressed() -> Result<()> {
+    test_pack(7, COMPR_ZLIB, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v8() -> Result<()> {
+    test_pack(8, COMPR_NONE, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v8_compressed() -> Result<()> {
+    test_pack(8, COMPR_ZLIB, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v9() -> Result<()> {
+    test_pack(9, COMPR_NONE, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v9_compressed() -> Result<()> {
+    test_pack(9, COMPR_ZLIB, Encryption::None)?;
+    Ok(())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// The round trip tests only compare the crate with itself, so check the bytes of
+// the inline record against FPakEntry::Serialize of the engine: versions 4 to 7
+// use the layout of version 3 and there is no extra field before the data.
+#[test]
+fn test_pack_inline_record_v4_to_v7() -> Result<()> {
+    let text = b"All work and no play makes Jack a dull boy.\n".repeat(200);
+
+    for version in 4..=7 {
+        let mut writer = PakWriter::new(Vec::new(), PackOptions {
+            version,
+            compression_method: COMPR_ZLIB,
+            compression_block_size: NonZeroU32::new(2048).unwrap(),
+            ..PackOptions::default()
+        })?;
+        writer.add_file("Text.txt", &text[..], &FileOptions::default())?;
+        let (data, pak) = writer.finish()?;
+
+        let record = &pak.index().records()[0];
+        let blocks = record.compression_blocks().as_ref().unwrap();
+        assert_eq!(blocks.len(), 5, "v{}", version);
+
+        // offset, size, uncompressed size, compression method, SHA-1, blocks, flags, block size
+        let start = record.offset() as usize;
+        let header_size = 8 + 8 + 8 + 4 + 20 + 4 + blocks.len() * 16 + 1 + 4;
+        assert_eq!(Pak::header_size(version, pak.variant(), record), header_size as u64, "v{}", version);
+
+        let header = &data[start..start + header_size];
+        assert_eq!(read_u64(header, 0), 0, "v{}", version);
+        assert_eq!(read_u64(header, 8), record.size(), "v{}", version);
+        assert_eq!(read_u64(header, 16), text.len() as u64, "v{}", version);
+        assert_eq!(read_u32(header, 24), COMPR_ZLIB, "v{}", version);
+        assert_eq!(&header[28..48], &record.sha1().unwrap()[..], "v{}", version);
+        assert_eq!(read_u32(header, 48), blocks.len() as u32, "v{}", version);
+
+        // version 5 made the block offsets relative to the record
+        let base_offset = if version < PAK_RELATIVE_COMPRESSION_OFFSET_VERSION { start } else { 0 };
+        let mut next_offset = base_offset + header_size;
+        for index in 0..blocks.len() {
+            let block_start = read_u64(header, 52 + index * 16) as usize;
+            let block_end = read_u64(header, 60 + index * 16) as usize;
+            assert_eq!(block_start, next_offset, "v{} block {}", version, index);
+            // every block starts with a zlib header
+            assert_eq!(data[block_start - base_offset + start], 0x78, "v{} block {}", version, index);
+            next_offset = block_end;
+        }
+        assert_eq!(next_offset, base_offset + header_size + record.size() as usize, "v{}", version);
+
+        let flags_offset = 52 + blocks.len() * 16;
+        assert_eq!(header[flags_offset], 0, "v{}", version);
+        assert_eq!(read_u32(header, flags_offset + 1), 2048, "v{}", version);
+
+        // footer: [encryption key GUID (v7)], encrypted index flag, magic, version, ...
+        let footer = &data[data.len() - Pak::footer_size(version) as usize..];
+        let footer = if version >= 7 {
+            assert_eq!(&footer[..16], &[0u8; 16], "v{}", version);
+            &footer[16..]
+        } else {
+            footer
+        };
+        assert_eq!(footer[0], 0, "v{}", version);
+        assert_eq!(read_u32(footer, 1), PAK_MAGIC, "v{}", version);
+        assert_eq!(read_u32(footer, 5), version, "v{}", version);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_frozen_index_v9() -> Result<()> {
+    let pak_path = "./frozen_index_v9-it.pak";
+
+    util::pack(pak_path, SOURCE_DIR, 9, COMPR_NONE)?;
+
+    // set the frozen index flag of the footer (after GUID, encrypted flag, magic,
+    // version, index offset, index size and index hash)
+    let mut data = std::fs::read(pak_path)?;
+    let offset = data.len() - Pak::footer_size(9) as usize + 16 + 1 + 4 + 4 + 8 + 8 + 20;
+    assert_eq!(data[offset], 0);
+    data[offset] = 1;
+    std::fs::write(pak_path, &data)?;
+
+    let error = Pak::from_path(pak_path, Options::default()).unwrap_err();
+    assert!(error.to_string().contains("frozen index is not supported"), "{}", error);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_u4pak"))
+        .args(["info", pak_path])
+        .output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Pak Version: 9\n"), "{}", stdout);
+    assert!(stdout.contains("Frozen Index: yes\n"), "{}", stdout);
+    assert!(stdout.contains("frozen index is not supported"), "{}", stdout);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v10() -> Result<()> {
+    test_pack(10, COMPR_NONE, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v10_compressed() -> Result<()> {
+    test_pack(10, COMPR_ZLIB, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v11() -> Result<()> {
+    test_pack(11, COMPR_NONE, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v11_compressed() -> Result<()> {
+    test_pack(11, COMPR_ZLIB, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_check_v11_corrupted() -> Result<()> {
+    let pak_path = "./check_v11_corrupted-it.pak";
+
+    util::pack(pak_path, SOURCE_DIR, 11, COMPR_ZLIB)?;
+
+    // flip a byte in the data of the first record
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let record = &pak.index().records()[0];
+    let offset = (record.offset() + Pak::header_size(pak.version(), pak.variant(), record)) as usize;
+    let mut data = std::fs::read(pak_path)?;
+    data[offset] ^= 0xff;
+    std::fs::write(pak_path, &data)?;
+
+    let error = util::check(pak_path).unwrap_err();
+    assert!(error.to_string().contains("checksum missmatch"), "{}", error);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_check_deep_v11_corrupted() -> Result<()> {
+    let pak_path = "./check_deep_v11_corrupted-it.pak";
+
+    util::pack(pak_path, SOURCE_DIR, 11, COMPR_ZLIB)?;
+
+    // flip a byte in the Adler-32 checksum at the end of the first compression block of a file
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let record = pak.index().records().iter().find(|record| record.compression_blocks().is_some()).unwrap();
+    let block = &record.compression_blocks().as_ref().unwrap()[0];
+    let offset = (record.offset() + block.end_offset - 1) as usize;
+    let mut data = std::fs::read(pak_path)?;
+    data[offset] ^= 0xff;
+    std::fs::write(pak_path, &data)?;
+
+    let error = util::check_deep(pak_path).unwrap_err();
+    assert!(error.to_string().contains("can't be decompressed"), "{}", error);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_check_v11_corrupted_directory_index() -> Result<()> {
+    let pak_path = "./check_v11_corrupted_directory_index-it.pak";
+
+    util::pack(pak_path, SOURCE_DIR, 11, COMPR_NONE)?;
+
+    // flip a byte in the last file name of the full directory index
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let info = pak.index().secondary_index().unwrap();
+    let offset = (info.full_directory_index_offset() + info.full_directory_index_size() - 6) as usize;
+    let mut data = std::fs::read(pak_path)?;
+    data[offset] ^= 0x20;
+    std::fs::write(pak_path, &data)?;
+
+    let error = util::check(pak_path).unwrap_err();
+    assert_eq!(error.path().as_ref().map(|path| path.to_string_lossy().into_owned()), Some("<full directory index>".to_string()));
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_conan_exiles() -> Result<()> {
+    let pak_path = "./pack_conan_exiles-it.pak";
+    let out_dir = "./pack_conan_exiles-it";
+    remove_dir_all_if_exists(out_dir)?;
+
+    util::pack_variant(pak_path, SOURCE_DIR, Variant::ConanExiles, 4, COMPR_NONE)?;
+    assert_eq!(util::check_variant(pak_path, Variant::ConanExiles)?, 0);
+    util::unpack_variant(pak_path, out_dir, Variant::ConanExiles, None)?;
+    util::validate(SOURCE_DIR, out_dir)?;
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_conan_exiles_compressed() -> Result<()> {
+    let pak_path = "./pack_conan_exiles_compressed-it.pak";
+    let out_dir = "./pack_conan_exiles_compressed-it";
+    remove_dir_all_if_exists(out_dir)?;
+
+    util::pack_variant(pak_path, SOURCE_DIR, Variant::ConanExiles, 4, COMPR_ZLIB)?;
+    assert_eq!(util::check_variant(pak_path, Variant::ConanExiles)?, 0);
+    util::unpack_variant(pak_path, out_dir, Variant::ConanExiles, None)?;
+    util::validate(SOURCE_DIR, out_dir)?;
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v8_compression_methods() -> Result<()> {
+    let pak_path = "./pack_v8_compression_methods-it.pak";
+
+    util::pack(pak_path, SOURCE_DIR, 8, COMPR_ZLIB)?;
+    assert_eq!(util::check(pak_path)?, 0);
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    remove_file_if_exists(pak_path)?;
+
+    assert_eq!(pak.compression_methods(), &["Zlib".to_string()]);
+    assert_eq!(pak.compression_method_name(COMPR_NONE), "-");
+    assert_eq!(pak.compression_method_name(1), "Zlib");
+    assert_eq!(pak.compression_method_name(2), "unknown");
+
+    for record in pak.index().records() {
+        assert!(record.compression_method() == COMPR_NONE || record.compression_method() == 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pack_v7_compression_methods() -> Result<()> {
+    let pak_path = "./pack_v7_compression_methods-it.pak";
+    let out_dir = "./pack_v7_compression_methods-it";
+    remove_dir_all_if_exists(out_dir)?;
+
+    util::pack(pak_path, SOURCE_DIR, 7, COMPR_ZLIB)?;
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    assert!(pak.compression_methods().is_empty());
+    assert_eq!(pak.compression_method_name(COMPR_ZLIB), "zlib");
+
+    let file = File::open(pak_path)?;
+    u4pak::unpack::unpack(&pak, &file, out_dir, UnpackOptions {
+        dirname_from_compression: true,
+        ..UnpackOptions::default()
+    })?;
+    for entry in std::fs::read_dir(out_dir)? {
+        let name = entry?.file_name();
+        assert!(name == "zlib" || name == "none", "unexpected directory: {:?}", name);
+    }
+    assert!(Path::new(out_dir).join("zlib").is_dir());
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v8_lz4() -> Result<()> {
+    let pak = test_pack(8, COMPR_LZ4, Encryption::None)?;
+    assert_eq!(pak.compression_methods(), &["LZ4".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_pack_v11_lz4() -> Result<()> {
+    test_pack(11, COMPR_LZ4, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v7_lz4() {
+    let pak_path = "./pack_v7_lz4-it.pak";
+
+    let result = util::pack(pak_path, SOURCE_DIR, 7, COMPR_LZ4);
+    let _ = remove_file_if_exists(pak_path);
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_pack_v7_gzip() -> Result<()> {
+    test_pack(7, COMPR_GZIP, Encryption::None)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v11_gzip() -> Result<()> {
+    let pak = test_pack(11, COMPR_GZIP, Encryption::None)?;
+    assert_eq!(pak.compression_methods(), &["Gzip".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_pack_v7_encrypted() -> Result<()> {
+    test_pack(7, COMPR_NONE, Encryption::All)?;
+    Ok(())
+}
+
+#[test]
+fn test_pack_v11_compressed_encrypted() -> Result<()> {
+    let pak = test_pack(11, COMPR_ZLIB, Encryption::All)?;
+    for record in pak.index().records() {
+        assert!(record.encrypted());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_pack_v11_encrypted_index_only() -> Result<()> {
+    let pak = test_pack(11, COMPR_ZLIB, Encryption::IndexOnly)?;
+    for record in pak.index().records() {
+        assert!(!record.encrypted());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_v11_wrong_encryption_key() -> Result<()> {
+    let pak_path = "./check_v11_wrong_encryption_key-it.pak";
+
+    util::pack_encrypted(pak_path, SOURCE_DIR, 11, COMPR_ZLIB, ENCRYPTION_KEY, false)?;
+    let error = util::check_encrypted(pak_path, OTHER_ENCRYPTION_KEY).unwrap_err();
+    assert!(error.to_string().contains("encryption key doesn't match"), "{}", error);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_check_v3_wrong_encryption_key() -> Result<()> {
+    let pak_path = "./check_v3_wrong_encryption_key-it.pak";
+
+    // version 3 has no encrypted index, so only the data is encrypted
+    util::pack_encrypted(pak_path, SOURCE_DIR, 3, COMPR_ZLIB, ENCRYPTION_KEY, false)?;
+    assert_eq!(util::check_encrypted(pak_path, ENCRYPTION_KEY)?, 0);
+    let error = util::check_encrypted(pak_path, OTHER_ENCRYPTION_KEY).unwrap_err();
+    assert!(error.to_string().contains("encryption key is probably wrong"), "{}", error);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_check_v3_uncompressed_wrong_encryption_key() -> Result<()> {
+    let pak_path = "./check_v3_uncompressed_wrong_encryption_key-it.pak";
+
+    // nothing to decompress, so the padding has to show that the key is wrong
+    util::pack_encrypted(pak_path, SOURCE_DIR, 3, COMPR_NONE, ENCRYPTION_KEY, false)?;
+    assert_eq!(util::check_encrypted(pak_path, ENCRYPTION_KEY)?, 0);
+    let error = util::check_encrypted(pak_path, OTHER_ENCRYPTION_KEY).unwrap_err();
+    assert!(error.to_string().contains("encryption key is probably wrong"), "{}", error);
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
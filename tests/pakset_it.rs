@@ -0,0 +1,198 @@
+mod util;
+
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::Path;
+
+use u4pak::pack::{FileOptions, PackOptions};
+use u4pak::pak::Options;
+use u4pak::pakset::PakPriority;
+use u4pak::unpack::UnpackOptions;
+use u4pak::{Pak, PakSet, PakWriter, Result};
+use util::remove_dir_all_if_exists;
+
+fn write_pak(path: impl AsRef<Path>, mount_point: &str, files: &[(&str, &str)]) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer = PakWriter::new(BufWriter::new(File::create(path)?), PackOptions {
+        version: 11,
+        mount_point: Some(mount_point),
+        ..PackOptions::default()
+    })?.with_path(path);
+
+    for (filename, data) in files {
+        writer.add_file(filename, data.as_bytes(), &FileOptions::default())?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+// The paks are written in an order that differs from their priority.
+fn write_paks(dir: &str) -> Result<()> {
+    remove_dir_all_if_exists(dir)?;
+    std::fs::create_dir_all(format!("{}/~mods", dir))?;
+
+    write_pak(format!("{}/pakchunk0-WindowsNoEditor_1_P.pak", dir), "../../../Game/", &[
+        ("Config/A.ini", "patch 2"),
+    ])?;
+    write_pak(format!("{}/pakchunk0-WindowsNoEditor.pak", dir), "../../../Game/", &[
+        ("Config/A.ini", "base"),
+        ("Config/B.ini", "base"),
+        ("Content/C.uasset", "base"),
+        ("Content/D.uasset", "base"),
+    ])?;
+    write_pak(format!("{}/pakchunk0-WindowsNoEditor_P.pak", dir), "../../../", &[
+        ("Game/Config/A.ini", "patch 1"),
+        ("Game/Config/B.ini", "patch 1"),
+    ])?;
+    write_pak(format!("{}/pakchunk1-WindowsNoEditor.pak", dir), "../../../Game/", &[
+        ("Content/C.uasset", "chunk 1"),
+        ("Content/E.uasset", "chunk 1"),
+    ])?;
+    write_pak(format!("{}/~mods/Mod.pak", dir), "../../../Game/", &[
+        ("Content/D.uasset", "mod"),
+    ])?;
+
+    Ok(())
+}
+
+fn expected() -> Vec<(&'static str, &'static str, &'static str)> {
+    vec![
+        ("Game/Config/A.ini",     "pakchunk0-WindowsNoEditor_1_P.pak", "patch 2"),
+        ("Game/Config/B.ini",     "pakchunk0-WindowsNoEditor_P.pak",   "patch 1"),
+        ("Game/Content/C.uasset", "pakchunk1-WindowsNoEditor.pak",     "chunk 1"),
+        ("Game/Content/D.uasset", "Mod.pak",                           "mod"),
+        ("Game/Content/E.uasset", "pakchunk1-WindowsNoEditor.pak",     "chunk 1"),
+    ]
+}
+
+fn pak_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_pak_priority() {
+    assert_eq!(PakPriority::from_filename("Game.pak", 0), PakPriority { patch: 0, chunk: 0, priority: 0 });
+    assert_eq!(PakPriority::from_filename("pakchunk12-WindowsNoEditor.pak", 3), PakPriority { patch: 0, chunk: 12, priority: 3 });
+    assert_eq!(PakPriority::from_filename("Paks/pakchunk1-WindowsNoEditor_P.pak", 0), PakPriority { patch: 1, chunk: 1, priority: 0 });
+    assert_eq!(PakPriority::from_filename("pakchunk0-WindowsNoEditor_2_P.PAK", 0), PakPriority { patch: 3, chunk: 0, priority: 0 });
+    assert_eq!(PakPriority::from_filename("pakchunk0-WindowsNoEditor_0_P.pak", 0), PakPriority { patch: 1, chunk: 0, priority: 0 });
+    assert_eq!(PakPriority::from_filename("My_Mod_P.pak", 0), PakPriority { patch: 1, chunk: 0, priority: 0 });
+
+    assert!(PakPriority::from_filename("pakchunk9-WindowsNoEditor.pak", 0) <
+            PakPriority::from_filename("pakchunk0-WindowsNoEditor_P.pak", 0));
+}
+
+#[test]
+fn test_pakset() -> Result<()> {
+    let dir = "./pakset-it";
+    let out_dir = "./pakset_unpack-it";
+    write_paks(dir)?;
+    remove_dir_all_if_exists(out_dir)?;
+
+    let set = PakSet::from_dir(dir, Options::default())?;
+    assert_eq!(set.layers().len(), 5);
+    assert_eq!(pak_name(set.layers().last().unwrap().path()), "pakchunk0-WindowsNoEditor_1_P.pak");
+
+    let entries: Vec<_> = set.entries()
+        .map(|entry| (entry.path(), pak_name(entry.layer().path())))
+        .collect();
+    let expected_entries: Vec<_> = expected().into_iter()
+        .map(|(path, pak, _)| (path, pak.to_string()))
+        .collect();
+    assert_eq!(entries, expected_entries);
+
+    for (path, _, data) in expected() {
+        let entry = set.get(&format!("/{}", path)).unwrap();
+        let mut buffer = String::new();
+        entry.layer().pak()
+            .open_entry(File::open(entry.layer().path())?, entry.record())?
+            .read_to_string(&mut buffer)?;
+        assert_eq!(buffer, data, "{}", path);
+    }
+    assert!(set.get("Game/Config/Missing.ini").is_none());
+
+    set.unpack(out_dir, UnpackOptions::default())?;
+    for (path, _, data) in expected() {
+        assert_eq!(std::fs::read_to_string(format!("{}/{}", out_dir, path))?, data, "{}", path);
+    }
+    remove_dir_all_if_exists(out_dir)?;
+
+    set.unpack(out_dir, UnpackOptions {
+        paths: Some(&["Game/Config"]),
+        ..UnpackOptions::default()
+    })?;
+    assert_eq!(std::fs::read_to_string(format!("{}/Game/Config/B.ini", out_dir))?, "patch 1");
+    assert!(!Path::new(&format!("{}/Game/Content", out_dir)).exists());
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_dir_all_if_exists(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_pakset_explicit_priority() -> Result<()> {
+    let dir = "./pakset_priority-it";
+    write_paks(dir)?;
+
+    let base = format!("{}/pakchunk0-WindowsNoEditor.pak", dir);
+    let modded = format!("{}/~mods/Mod.pak", dir);
+
+    // without explicit priority the path of Mod.pak sorts last
+    let mut set = PakSet::new();
+    set.insert(&modded, Pak::from_path(&modded, Options::default())?, 0);
+    set.insert(&base, Pak::from_path(&base, Options::default())?, 1);
+    assert_eq!(set.len(), 4);
+
+    let entry = set.get("Game/Content/D.uasset").unwrap();
+    assert_eq!(pak_name(entry.layer().path()), "pakchunk0-WindowsNoEditor.pak");
+    assert_eq!(entry.layer().priority().priority, 1);
+
+    remove_dir_all_if_exists(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_pakset_list_command() -> Result<()> {
+    let dir = "./pakset_list_command-it";
+    write_paks(dir)?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_u4pak"))
+        .args(["list", "--no-header", dir, "Game/Content"])
+        .output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rows: Vec<Vec<&str>> = stdout.lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(rows.len(), 3, "unexpected output: {}", stdout);
+    assert_eq!(&rows[0][4..], &["pakchunk1-WindowsNoEditor.pak", "Game/Content/C.uasset"]);
+
+    remove_dir_all_if_exists(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_pakset_insert_dir() -> Result<()> {
+    let dir = "./pakset_insert_dir-it";
+    write_paks(dir)?;
+
+    // like `u4pak mount Paks Paks/~mods`, where later arguments win
+    let mut set = PakSet::new();
+    set.insert_dir(format!("{}/~mods", dir), Options::default(), 1)?;
+    set.insert_dir(dir, Options::default(), 0)?;
+    assert_eq!(set.layers().len(), 6);
+
+    for entry in set.entries() {
+        assert!(std::ptr::eq(entry.layer(), &set.layers()[entry.layer_index()]));
+    }
+    assert_eq!(pak_name(set.get("Game/Content/D.uasset").unwrap().layer().path()), "Mod.pak");
+    assert_eq!(set.get("Game/Content/D.uasset").unwrap().layer().priority().priority, 1);
+
+    assert!(set.insert_dir(format!("{}/missing", dir), Options::default(), 2).is_err());
+    assert_eq!(set.layers().len(), 6);
+
+    remove_dir_all_if_exists(dir)?;
+    Ok(())
+}
@@ -0,0 +1,46 @@
+// Stand-in codec plugin for tests. XORs the data and drops trailing zeros so
+// the "compressed" data is smaller than the input.
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+
+const char *u4pak_codec_name(void) {
+    return "Xor";
+}
+
+ptrdiff_t u4pak_codec_compress_block(
+        const uint8_t *input, size_t input_size,
+        uint8_t *output, size_t output_capacity,
+        uint32_t level) {
+    (void)level;
+
+    size_t size = input_size;
+    while (size > 0 && input[size - 1] == 0) {
+        -- size;
+    }
+
+    if (size > output_capacity) {
+        return (ptrdiff_t)size;
+    }
+
+    for (size_t index = 0; index < size; ++ index) {
+        output[index] = input[index] ^ 0x5a;
+    }
+
+    return (ptrdiff_t)size;
+}
+
+ptrdiff_t u4pak_codec_decompress_block(
+        const uint8_t *input, size_t input_size,
+        uint8_t *output, size_t output_size) {
+    if (input_size > output_size) {
+        return -1;
+    }
+
+    for (size_t index = 0; index < input_size; ++ index) {
+        output[index] = input[index] ^ 0x5a;
+    }
+    memset(output + input_size, 0, output_size - input_size);
+
+    return (ptrdiff_t)output_size;
+}
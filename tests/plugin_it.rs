@@ -0,0 +1,89 @@
+#![cfg(unix)]
+
+mod util;
+
+use std::fs::File;
+use std::process::Command;
+use std::sync::Arc;
+
+use u4pak::codec::CodecRegistry;
+use u4pak::pack::{PackOptions, PackPath};
+use u4pak::pak::{COMPR_CUSTOM, Options};
+use u4pak::plugin::load_codec_plugin;
+use u4pak::unpack::UnpackOptions;
+use u4pak::{Error, Pak, Result};
+use util::{remove_dir_all_if_exists, remove_file_if_exists};
+
+fn build_plugin(path: &str) -> Result<()> {
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(compiler)
+        .args(["-shared", "-fPIC", "-o", path, "./tests/plugin/xor_codec.c"])
+        .status()?;
+
+    if !status.success() {
+        return Err(Error::new(format!("failed to build codec plugin: {}", status)).with_path(path));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_codec_plugin() -> Result<()> {
+    let plugin_path = "./xor_codec-it.so";
+    let pak_path = "./codec_plugin-it.pak";
+    let out_dir = "./codec_plugin-it";
+    let source_dir = "./codec_plugin-it-source";
+    remove_dir_all_if_exists(out_dir)?;
+    remove_dir_all_if_exists(source_dir)?;
+
+    build_plugin(plugin_path)?;
+
+    std::fs::create_dir_all(source_dir)?;
+    let mut data = b"some data that is padded with a lot of zeros".to_vec();
+    data.resize(200_000, 0);
+    std::fs::write(format!("{}/padded.bin", source_dir), &data)?;
+    std::fs::write(format!("{}/small.txt", source_dir), b"too small to be compressed")?;
+
+    let mut codecs = CodecRegistry::default();
+    let method = load_codec_plugin(&mut codecs, plugin_path)?;
+    assert!(method > COMPR_CUSTOM);
+    assert_eq!(codecs.by_name("xor").map(|codec| codec.method()), Some(method));
+    let codecs = Arc::new(codecs);
+
+    let mut source = PackPath::new(source_dir.to_string());
+    source.rename = Some("/".to_string());
+
+    u4pak::pack::pack(pak_path, &[source], PackOptions {
+        version: 11,
+        compression_method: method,
+        codecs: codecs.clone(),
+        ..PackOptions::default()
+    })?;
+
+    let pak = Pak::from_path(pak_path, Options {
+        codecs,
+        ..Options::default()
+    })?;
+    assert_eq!(pak.compression_methods(), &["Xor".to_string()]);
+
+    let file = File::open(pak_path)?;
+    u4pak::unpack::unpack(&pak, &file, out_dir, UnpackOptions::default())?;
+    util::validate(source_dir, out_dir)?;
+
+    // without the plugin the compressed file can't be unpacked
+    let pak = Pak::from_path(pak_path, Options::default())?;
+    let error = u4pak::unpack::unpack(&pak, &file, out_dir, UnpackOptions::default()).unwrap_err();
+    assert!(error.to_string().contains("unsupported compression method: Xor"), "{}", error);
+
+    remove_dir_all_if_exists(out_dir)?;
+    remove_dir_all_if_exists(source_dir)?;
+    remove_file_if_exists(pak_path)?;
+    remove_file_if_exists(plugin_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_codec_plugin_missing() {
+    let mut codecs = CodecRegistry::default();
+    assert!(load_codec_plugin(&mut codecs, "./does-not-exist-it.so").is_err());
+}
@@ -0,0 +1,87 @@
+mod util;
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use memmap2::Mmap;
+use u4pak::check::CheckOptions;
+use u4pak::pak::{COMPR_NONE, COMPR_ZLIB, Options};
+use u4pak::unpack::UnpackOptions;
+use u4pak::{Pak, ReadAt, ReadAtCursor, Result};
+use util::{remove_dir_all_if_exists, remove_file_if_exists};
+
+const SOURCE_DIR: &str = "./pak-examples/original-files";
+
+fn check_and_unpack<R>(reader: &R, out_dir: &str) -> Result<()>
+where R: ReadAt + Sync + ?Sized {
+    remove_dir_all_if_exists(out_dir)?;
+
+    let pak = Pak::from_reader(&mut ReadAtCursor::new(reader), Options::default())?;
+
+    u4pak::check::check(&pak, reader, CheckOptions {
+        abort_on_error: true,
+        deep: true,
+        ..CheckOptions::default()
+    })?;
+
+    u4pak::unpack::unpack(&pak, reader, out_dir, UnpackOptions::default())?;
+    util::validate(SOURCE_DIR, out_dir)?;
+
+    remove_dir_all_if_exists(out_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_read_at_memory() -> Result<()> {
+    let pak_path = "./read_at_memory-it.pak";
+    util::pack(pak_path, SOURCE_DIR, 11, COMPR_ZLIB)?;
+    let data = std::fs::read(pak_path)?;
+    remove_file_if_exists(pak_path)?;
+
+    check_and_unpack(&data, "./read_at_memory-it")?;
+
+    // a pak nested in some other container
+    let mut container = vec![0xffu8; 1000];
+    container.extend_from_slice(&data);
+    container.extend_from_slice(&[0xff; 100]);
+    check_and_unpack(&container[1000..1000 + data.len()], "./read_at_nested-it")?;
+
+    Ok(())
+}
+
+#[test]
+fn test_read_at_mmap() -> Result<()> {
+    let pak_path = "./read_at_mmap-it.pak";
+    util::pack(pak_path, SOURCE_DIR, 3, COMPR_NONE)?;
+
+    let file = File::open(pak_path)?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    check_and_unpack(&mmap, "./read_at_mmap-it")?;
+    drop(mmap);
+
+    // files are read at their offsets, not at the file cursor
+    check_and_unpack(&file, "./read_at_file-it")?;
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_read_at_cursor() -> Result<()> {
+    let data: Vec<u8> = (0..100u8).collect();
+
+    let mut buffer = [0u8; 10];
+    assert_eq!(data.read_at(&mut buffer, 95)?, 5);
+    assert_eq!(&buffer[..5], &data[95..]);
+    assert_eq!(data.read_at(&mut buffer, 100)?, 0);
+    assert!(data.read_exact_at(&mut buffer, 91).is_err());
+
+    let mut cursor = ReadAtCursor::new(&data[..]);
+    assert_eq!(cursor.seek(SeekFrom::End(-10))?, 90);
+    let mut tail = Vec::new();
+    cursor.read_to_end(&mut tail)?;
+    assert_eq!(tail, &data[90..]);
+    assert!(cursor.seek(SeekFrom::Current(-101)).is_err());
+
+    Ok(())
+}
@@ -1,9 +1,14 @@
+// Not every test crate uses every helper.
+#![allow(dead_code)]
+
 use std::fs::File;
 use std::io::BufReader;
 use std::num::NonZeroUsize;
 use std::path::Path;
 
+use u4pak::check::CheckOptions;
 use u4pak::index::Encoding;
+use u4pak::pack::{PackOptions, PackPath};
 use u4pak::pak::Options;
 use u4pak::unpack::UnpackOptions;
 use u4pak::util::{sha1_digest};
@@ -21,7 +26,126 @@ pub fn remove_dir_all_if_exists(path: impl AsRef<std::path::Path>) -> std::io::R
     Ok(())
 }
 
+pub fn remove_file_if_exists(path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+    if let Err(error) = std::fs::remove_file(path) {
+        if let std::io::ErrorKind::NotFound = error.kind() {
+            return Ok(());
+        }
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+pub fn pack(path: &str, source_dir: &str, version: u32, compression_method: u32) -> Result<()> {
+    pack_variant(path, source_dir, Variant::Standard, version, compression_method)
+}
+
+pub fn pack_variant(path: &str, source_dir: &str, variant: Variant, version: u32, compression_method: u32) -> Result<()> {
+    let mut source = PackPath::new(source_dir.to_string());
+    source.rename = Some("/".to_string());
+
+    u4pak::pack::pack(
+        path,
+        &[source],
+        PackOptions {
+            variant,
+            version,
+            compression_method,
+            ..PackOptions::default()
+        },
+    )?;
+
+    Ok(())
+}
+
+pub fn pack_encrypted(path: &str, source_dir: &str, version: u32, compression_method: u32, encryption_key: &str, encrypt_index_only: bool) -> Result<()> {
+    let mut source = PackPath::new(source_dir.to_string());
+    source.rename = Some("/".to_string());
+
+    u4pak::pack::pack(
+        path,
+        &[source],
+        PackOptions {
+            version,
+            compression_method,
+            encryption_key: Some(base64::decode(encryption_key).expect("Failed to parse encryption key.")),
+            encrypt_index_only,
+            ..PackOptions::default()
+        },
+    )?;
+
+    Ok(())
+}
+
+pub fn check(path: &str) -> Result<usize> {
+    check_variant(path, Variant::Standard)
+}
+
+pub fn check_variant(path: &str, variant: Variant) -> Result<usize> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => return Err(Error::io_with_path(error, path)),
+    };
+    let mut reader = BufReader::new(&file);
+
+    let pak = Pak::from_reader(&mut reader, Options {
+        variant,
+        ..Options::default()
+    })?;
+
+    drop(reader);
+
+    u4pak::check::check(&pak, &file, CheckOptions {
+        variant,
+        abort_on_error: true,
+        ..CheckOptions::default()
+    })
+}
+
+pub fn check_deep(path: &str) -> Result<usize> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => return Err(Error::io_with_path(error, path)),
+    };
+    let mut reader = BufReader::new(&file);
+
+    let pak = Pak::from_reader(&mut reader, Options::default())?;
+
+    drop(reader);
+
+    u4pak::check::check(&pak, &file, CheckOptions {
+        abort_on_error: true,
+        deep: true,
+        ..CheckOptions::default()
+    })
+}
+
+pub fn check_encrypted(path: &str, encryption_key: &str) -> Result<usize> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => return Err(Error::io_with_path(error, path)),
+    };
+    let mut reader = BufReader::new(&file);
+
+    let pak = Pak::from_reader(&mut reader, Options {
+        encryption_key: Some(base64::decode(encryption_key).expect("Failed to parse encryption key.")),
+        ..Options::default()
+    })?;
+
+    drop(reader);
+
+    u4pak::check::check(&pak, &file, CheckOptions {
+        abort_on_error: true,
+        ..CheckOptions::default()
+    })
+}
+
 pub fn unpack(path: &str, outdir: &str, encryption: Option<String>) -> Result<()> {
+    unpack_variant(path, outdir, Variant::default(), encryption)
+}
+
+pub fn unpack_variant(path: &str, outdir: &str, variant: Variant, encryption: Option<String>) -> Result<()> {
     let encryption_key = if let Some(key) = encryption {
         Some(
             base64::decode(
@@ -34,20 +158,21 @@ pub fn unpack(path: &str, outdir: &str, encryption: Option<String>) -> Result<()
         None
     };
 
-    let mut file = match File::open(path) {
+    let file = match File::open(path) {
         Ok(file) => file,
         Err(error) => return Err(Error::io_with_path(error, path)),
     };
-    let mut reader = BufReader::new(&mut file);
+    let mut reader = BufReader::new(&file);
 
     let pak = Pak::from_reader(
         &mut reader,
         Options {
-            variant: Variant::default(),
+            variant,
             ignore_magic: false,
             encoding: Encoding::default(),
             force_version: None,
             encryption_key: encryption_key.clone(),
+            ..Options::default()
         },
     )?;
 
@@ -55,7 +180,7 @@ pub fn unpack(path: &str, outdir: &str, encryption: Option<String>) -> Result<()
 
     u4pak::unpack::unpack(
         &pak,
-        &mut file,
+        &file,
         outdir,
         UnpackOptions {
             dirname_from_compression: false,
@@ -0,0 +1,144 @@
+mod util;
+
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::num::{NonZeroU32, NonZeroUsize};
+
+use u4pak::pack::{FileOptions, PackOptions, PackPath};
+use u4pak::pak::{COMPR_NONE, COMPR_ZLIB, Options};
+use u4pak::{Pak, PakWriter, Result};
+use util::remove_file_if_exists;
+
+fn files() -> Vec<(&'static str, Vec<u8>)> {
+    let text = b"All work and no play makes Jack a dull boy.\n".repeat(1000);
+    let noise: Vec<u8> = (0..20000u32).map(|index| (index.wrapping_mul(2654435761) >> 13) as u8).collect();
+    vec![
+        ("Content/Text.txt", text),
+        ("Content/Noise.bin", noise),
+        ("Empty.txt", Vec::new()),
+        ("/Config/Small.ini", b"[Core]\nkey=value\n".to_vec()),
+    ]
+}
+
+fn write_pak(version: u32, compression_method: u32) -> Result<Vec<u8>> {
+    let mut writer = PakWriter::new(Vec::new(), PackOptions {
+        version,
+        compression_method,
+        compression_block_size: NonZeroU32::new(4096).unwrap(),
+        ..PackOptions::default()
+    })?.with_path("writer-it.pak");
+
+    for (filename, data) in files() {
+        // the noise doesn't compress, so it is stored uncompressed
+        let file_options = if filename.ends_with(".bin") {
+            FileOptions { compression_method: COMPR_NONE, ..FileOptions::default() }
+        } else {
+            FileOptions::default()
+        };
+        writer.add_file(filename, &data[..], &file_options)?;
+    }
+
+    let (data, pak) = writer.finish()?;
+    assert_eq!(pak.index().records().len(), files().len());
+
+    Ok(data)
+}
+
+fn validate(data: &[u8]) -> Result<()> {
+    let mut reader = Cursor::new(data);
+    let pak = Pak::from_reader(&mut reader, Options::default())?;
+    let records = pak.index().records();
+    assert_eq!(records.len(), files().len());
+
+    for (filename, expected) in files() {
+        let filename = filename.trim_start_matches('/');
+        let record = records.iter().find(|record| record.filename() == filename)
+            .unwrap_or_else(|| panic!("{}: missing in pak", filename));
+
+        let mut data = Vec::new();
+        pak.open_entry(&mut reader, record)?.read_to_end(&mut data)?;
+        assert!(data == expected, "{}: data differs", filename);
+    }
+
+    Ok(())
+}
+
+fn test_writer(version: u32, compression_method: u32) -> Result<()> {
+    let data = write_pak(version, compression_method)?;
+    validate(&data)?;
+
+    let pak_path = format!("./writer_v{}-it.pak", version);
+    std::fs::write(&pak_path, &data)?;
+    if compression_method == COMPR_NONE {
+        util::check(&pak_path)?;
+    } else {
+        util::check_deep(&pak_path)?;
+    }
+    remove_file_if_exists(&pak_path)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_writer_v1() -> Result<()> {
+    test_writer(1, COMPR_NONE)
+}
+
+#[test]
+fn test_writer_v3() -> Result<()> {
+    test_writer(3, COMPR_ZLIB)
+}
+
+#[test]
+fn test_writer_v11() -> Result<()> {
+    test_writer(11, COMPR_ZLIB)
+}
+
+#[test]
+fn test_writer_same_as_pack() -> Result<()> {
+    let pak_path = "./writer_same_as_pack-it.pak";
+    let source_dir = "./pak-examples/original-files";
+
+    let mut source = PackPath::new(source_dir.to_string());
+    source.rename = Some("/".to_string());
+    let options = PackOptions {
+        version: 11,
+        compression_method: COMPR_ZLIB,
+        thread_count: NonZeroUsize::new(1).unwrap(),
+        ..PackOptions::default()
+    };
+    let pak = u4pak::pack::pack(pak_path, &[source], options.clone())?;
+
+    // same files in the same order
+    let mut writer = PakWriter::new(Vec::new(), options)?.with_path(pak_path);
+    for record in pak.index().records() {
+        let file = File::open(format!("{}/{}", source_dir, record.filename()))?;
+        writer.add_file(record.filename(), file, &FileOptions::default())?;
+    }
+    let (data, _) = writer.finish()?;
+
+    assert!(data == std::fs::read(pak_path)?, "written pak differs from packed pak");
+
+    remove_file_if_exists(pak_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_writer_illegal_filenames() -> Result<()> {
+    let mut writer = PakWriter::new(Vec::new(), PackOptions::default())?;
+
+    writer.add_file("foo/bar.txt", &b"bar"[..], &FileOptions::default())?;
+    assert!(writer.add_file("/foo//bar.txt", &b"baz"[..], &FileOptions::default()).is_err());
+    assert!(writer.add_file("/", &b""[..], &FileOptions::default()).is_err());
+
+    // compression is only supported starting with version 2
+    let mut writer = PakWriter::new(Vec::new(), PackOptions { version: 1, ..PackOptions::default() })?;
+    let file_options = FileOptions { compression_method: COMPR_ZLIB, ..FileOptions::default() };
+    assert!(writer.add_file("foo.txt", &b"foo"[..], &file_options).is_err());
+
+    let (data, pak) = writer.finish()?;
+    assert!(pak.index().records().is_empty());
+    Pak::from_reader(&mut Cursor::new(data), Options::default())?;
+
+    Ok(())
+}